@@ -0,0 +1,117 @@
+use crate::currency;
+use clap::ValueEnum;
+
+/// How amounts write their decimal and thousands separators.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum NumberFormat {
+    /// Guess the format from every amount of the file.
+    Auto,
+    /// Decimal point, like 1,234.56.
+    Point,
+    /// Decimal comma, like 1.234,56.
+    Comma,
+}
+
+/// Removes currency symbols and codes, the spaces and apostrophes used as thousands
+/// separators, and the sign, returning whether the amount is negative.
+fn clean(value: &str) -> (bool, String) {
+    let value = currency::strip(value).replace('$', "");
+    let value = value.trim();
+    let (negative, value) =
+        if let Some(x) = value.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+            (true, x)
+        } else if let Some(x) = value.strip_suffix('-') {
+            (true, x)
+        } else if let Some(x) = value.strip_prefix('-') {
+            (true, x)
+        } else {
+            (false, value)
+        };
+    let digits = value
+        .chars()
+        .filter(|x| !x.is_whitespace() && *x != '\'')
+        .collect::<String>();
+    (negative, digits)
+}
+
+/// Format of a single amount, when its separators make it unambiguous.
+fn guess(value: &str) -> Option<NumberFormat> {
+    let (_, value) = clean(value);
+    let decimals = |separator: char| value.rsplit(separator).next().map_or(0, |x| x.len());
+    match (value.matches(',').count(), value.matches('.').count()) {
+        (0, 0) => None,
+        (commas, 0) if commas > 1 => Some(NumberFormat::Point),
+        (0, dots) if dots > 1 => Some(NumberFormat::Comma),
+        (_, 0) => (decimals(',') != 3).then_some(NumberFormat::Comma),
+        (0, _) => (decimals('.') != 3).then_some(NumberFormat::Point),
+        _ if value.rfind(',') > value.rfind('.') => Some(NumberFormat::Comma),
+        _ => Some(NumberFormat::Point),
+    }
+}
+
+/// Picks the format most amounts agree on, a decimal point when nothing tells them apart.
+pub fn detect<'a>(values: impl Iterator<Item = &'a str>) -> NumberFormat {
+    let (points, commas) = values
+        .filter_map(guess)
+        .fold((0, 0), |(points, commas), x| match x {
+            NumberFormat::Comma => (points, commas + 1),
+            _ => (points + 1, commas),
+        });
+    if commas > points {
+        NumberFormat::Comma
+    } else {
+        NumberFormat::Point
+    }
+}
+
+/// Parses an amount such as `$1,234.56`, `1.234,56 €`, `(12.00)` or `12.00-`.
+pub fn parse(value: &str, format: NumberFormat) -> Option<f64> {
+    let format = match format {
+        NumberFormat::Auto => guess(value).unwrap_or(NumberFormat::Point),
+        x => x,
+    };
+    let (negative, value) = clean(value);
+    let value = match format {
+        NumberFormat::Comma => value.replace('.', "").replace(',', "."),
+        _ => value.replace(',', ""),
+    };
+    let amount = value.parse::<f64>().ok().filter(|x| x.is_finite())?;
+    Some(if negative { -amount } else { amount })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(" $1,234.56 ", NumberFormat::Point), Some(1234.56));
+        assert_eq!(parse("1.234,56 €", NumberFormat::Comma), Some(1234.56));
+        assert_eq!(parse("1 234,56 MXN", NumberFormat::Comma), Some(1234.56));
+        assert_eq!(parse("1,234.00MXN", NumberFormat::Point), Some(1234.0));
+        assert_eq!(parse("1'234.56", NumberFormat::Point), Some(1234.56));
+        assert_eq!(parse("($12.00)", NumberFormat::Point), Some(-12.0));
+        assert_eq!(parse("12.00-", NumberFormat::Point), Some(-12.0));
+        assert_eq!(parse("-$12.00", NumberFormat::Point), Some(-12.0));
+        assert_eq!(parse("$-12.00", NumberFormat::Point), Some(-12.0));
+        assert_eq!(parse("1,234", NumberFormat::Comma), Some(1.234));
+        assert_eq!(parse("1,234", NumberFormat::Point), Some(1234.0));
+        assert_eq!(parse("12,5", NumberFormat::Auto), Some(12.5));
+        assert_eq!(parse("n/a", NumberFormat::Point), None);
+        assert_eq!(parse("", NumberFormat::Point), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect(["1.234,56", "12,00", "1,234"].into_iter()),
+            NumberFormat::Comma
+        );
+        assert_eq!(
+            detect(["1,234.56", "12.00", "1.234"].into_iter()),
+            NumberFormat::Point
+        );
+        assert_eq!(detect(["1.234", "1,234"].into_iter()), NumberFormat::Point);
+        assert_eq!(detect(["1.234.567"].into_iter()), NumberFormat::Comma);
+    }
+}
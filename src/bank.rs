@@ -0,0 +1,303 @@
+use crate::amount::{self, NumberFormat};
+use crate::date::Date;
+use crate::reader::header_key;
+use std::path::Path;
+
+const DATE_NAMES: [&str; 6] = [
+    "date",
+    "fecha",
+    "fecha operacion",
+    "fecha de operacion",
+    "posted",
+    "transaction date",
+];
+const DESCRIPTION_NAMES: [&str; 7] = [
+    "description",
+    "descripcion",
+    "concepto",
+    "payee",
+    "memo",
+    "detalle",
+    "merchant",
+];
+const AMOUNT_NAMES: [&str; 4] = ["amount", "importe", "monto", "cantidad"];
+const DEBIT_NAMES: [&str; 6] = ["debit", "cargo", "cargos", "debito", "retiro", "withdrawal"];
+const CREDIT_NAMES: [&str; 6] = [
+    "credit", "abono", "abonos", "credito", "deposito", "deposit",
+];
+
+/// A movement of a bank or card statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub date: Date,
+    /// Money spent, negative for deposits and refunds.
+    pub amount: f64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatementFormat {
+    Ofx,
+    Qif,
+    Csv,
+}
+
+impl StatementFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ofx" | "qfx" => Some(StatementFormat::Ofx),
+            "qif" => Some(StatementFormat::Qif),
+            "csv" => Some(StatementFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `27/12/2024`, `2024-12-27` and the other receipt formats, and the US `12/27/2024`
+/// or `12/27'24` written by QIF files. With `month_first` an ambiguous date like
+/// `05/06/2024` is read as May 6.
+fn parse_date(value: &str, month_first: bool) -> Option<Date> {
+    let value = value.trim().replace('\'', "/");
+    let parts = value
+        .split('/')
+        .map(|x| x.trim().parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>();
+    let numeric = |first: u32, second: u32, year: u32| {
+        let year = if year < 100 { year + 2000 } else { year } as i32;
+        match month_first {
+            true => Date::new(year, first, second).or_else(|| Date::new(year, second, first)),
+            false => Date::new(year, second, first).or_else(|| Date::new(year, first, second)),
+        }
+    };
+    match parts.as_deref() {
+        Some([first, second, year]) => numeric(*first, *second, *year),
+        _ => Date::parse(&value),
+    }
+}
+
+pub fn read_statement(content: &str, format: StatementFormat) -> Result<Vec<Transaction>, String> {
+    match format {
+        StatementFormat::Ofx => Ok(read_ofx(content)),
+        StatementFormat::Qif => Ok(read_qif(content)),
+        StatementFormat::Csv => read_csv(content),
+    }
+}
+
+/// Value of an OFX tag, which in the SGML flavour is not closed.
+fn ofx_value(block: &str, tag: &str) -> Option<String> {
+    let start = block.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let rest = &block[start..];
+    let end = rest.find(['<', '\n', '\r']).unwrap_or(rest.len());
+    Some(rest[..end].trim().to_owned()).filter(|x| !x.is_empty())
+}
+
+/// Reads the `<STMTTRN>` entries of an OFX or QFX statement.
+pub fn read_ofx(content: &str) -> Vec<Transaction> {
+    content
+        .split("<STMTTRN>")
+        .skip(1)
+        .filter_map(|block| {
+            let date = ofx_value(block, "DTPOSTED")?;
+            let date = Date::new(
+                date.get(0..4)?.parse().ok()?,
+                date.get(4..6)?.parse().ok()?,
+                date.get(6..8)?.parse().ok()?,
+            )?;
+            let amount = amount::parse(&ofx_value(block, "TRNAMT")?, NumberFormat::Auto)?;
+            let description = ofx_value(block, "NAME")
+                .or_else(|| ofx_value(block, "MEMO"))
+                .unwrap_or_default();
+            Some(Transaction {
+                date,
+                amount: -amount,
+                description,
+            })
+        })
+        .collect()
+}
+
+/// Reads the records of a QIF file, each one ending with a `^` line.
+pub fn read_qif(content: &str) -> Vec<Transaction> {
+    let mut transactions = Vec::new();
+    let (mut date, mut amount, mut payee, mut memo) = (None, None, None, None);
+    for line in content.lines().map(str::trim) {
+        let (code, value) = line.split_at(line.chars().next().map_or(0, |x| x.len_utf8()));
+        match code {
+            "D" => date = parse_date(value, true),
+            "T" | "U" => amount = amount::parse(value, NumberFormat::Auto),
+            "P" => payee = Some(value.trim().to_owned()),
+            "M" => memo = Some(value.trim().to_owned()),
+            "^" => {
+                if let (Some(date), Some(amount)) = (date, amount) {
+                    transactions.push(Transaction {
+                        date,
+                        amount: -amount,
+                        description: payee.clone().or(memo.clone()).unwrap_or_default(),
+                    });
+                }
+                (date, amount, payee, memo) = (None, None, None, None);
+            }
+            _ => {}
+        }
+    }
+    transactions
+}
+
+/// Reads a bank CSV export with a header row. Amounts come either from a single signed
+/// column, where charges are negative, or from separate debit and credit columns.
+pub fn read_csv(content: &str) -> Result<Vec<Transaction>, String> {
+    let header = content.lines().next().unwrap_or("");
+    let delimiter = if header.matches(';').count() > header.matches(',').count() {
+        b';'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(|x| x.to_string())?.clone();
+    let find = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|x| names.contains(&header_key(x).as_str()))
+    };
+    let date_column = find(&DATE_NAMES).ok_or("no date column")?;
+    let description_column = find(&DESCRIPTION_NAMES);
+    let amount_column = find(&AMOUNT_NAMES);
+    let debit_column = find(&DEBIT_NAMES);
+    let credit_column = find(&CREDIT_NAMES);
+    if amount_column.is_none() && debit_column.is_none() {
+        return Err("no amount or debit column".to_owned());
+    }
+
+    let records = reader
+        .records()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|x| x.to_string())?;
+    let amount_columns = [amount_column, debit_column, credit_column];
+    let format = amount::detect(
+        records
+            .iter()
+            .flat_map(|x| amount_columns.iter().flatten().filter_map(|c| x.get(*c))),
+    );
+    let value = |record: &csv::StringRecord, column: Option<usize>| {
+        column
+            .and_then(|x| record.get(x))
+            .and_then(|x| amount::parse(x, format))
+    };
+    Ok(records
+        .iter()
+        .filter_map(|record| {
+            let date = parse_date(record.get(date_column)?, false)?;
+            let amount = match amount_column {
+                Some(_) => -value(record, amount_column)?,
+                None => {
+                    let debit = value(record, debit_column).map(f64::abs);
+                    let credit = value(record, credit_column).map(f64::abs);
+                    if debit.is_none() && credit.is_none() {
+                        return None;
+                    }
+                    debit.unwrap_or(0.0) - credit.unwrap_or(0.0)
+                }
+            };
+            Some(Transaction {
+                date,
+                amount,
+                description: description_column
+                    .and_then(|x| record.get(x))
+                    .map_or(String::new(), |x| x.trim().to_owned()),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_ofx() {
+        let transactions = read_ofx(
+            "OFXHEADER:100
+            <OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+            <STMTTRN>
+            <TRNTYPE>DEBIT
+            <DTPOSTED>20241227120000.000[-6:CST]
+            <TRNAMT>-653.00
+            <NAME>LA CANTINA
+            </STMTTRN>
+            <STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20241228<TRNAMT>100.00<MEMO>DEVOLUCION</STMTTRN>
+            </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>",
+        );
+        assert_eq!(
+            transactions,
+            [
+                Transaction {
+                    date: Date::new(2024, 12, 27).unwrap(),
+                    amount: 653.0,
+                    description: "LA CANTINA".to_owned(),
+                },
+                Transaction {
+                    date: Date::new(2024, 12, 28).unwrap(),
+                    amount: -100.0,
+                    description: "DEVOLUCION".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_qif() {
+        let transactions = read_qif(
+            "!Type:CCard
+            D12/27'24
+            T-1,653.00
+            PWALMART
+            ^
+            D05/06/2025
+            T-20.00
+            MOXXO
+            ^
+            ",
+        );
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].date, Date::new(2024, 12, 27).unwrap());
+        assert_eq!(transactions[0].amount, 1653.0);
+        assert_eq!(transactions[0].description, "WALMART");
+        assert_eq!(transactions[1].date, Date::new(2025, 5, 6).unwrap());
+        assert_eq!(transactions[1].description, "OXXO");
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let transactions = read_csv(
+            "Fecha;Descripción;Cargo;Abono\n\
+             27/12/2024;\"LA CANTINA; CDMX\";653,00;\n\
+             28/12/2024;DEVOLUCION;;100,00\n\
+             29/12/2024;SALDO;;\n",
+        )
+        .unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].description, "LA CANTINA; CDMX");
+        assert_eq!(transactions[0].amount, 653.0);
+        assert_eq!(transactions[1].amount, -100.0);
+
+        let transactions =
+            read_csv("Date,Description,Amount\n12/27/2024,Pub,\"-1,234.50\"\n").unwrap();
+        assert_eq!(transactions[0].date, Date::new(2024, 12, 27).unwrap());
+        assert_eq!(transactions[0].amount, 1234.5);
+
+        assert!(read_csv("Fecha,Concepto\n27/12/2024,Pub\n").is_err());
+    }
+
+    #[test]
+    fn test_statement_format() {
+        assert_eq!(
+            StatementFormat::from_path(Path::new("card.QFX")),
+            Some(StatementFormat::Ofx)
+        );
+        assert_eq!(StatementFormat::from_path(Path::new("card.txt")), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::history::Purchase;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Products to buy with their quantities, read from a TOML file such as:
+///
+/// ```toml
+/// [items]
+/// Cerveza = 6
+/// "Vino Tinto" = 1
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Basket {
+    pub items: BTreeMap<String, f64>,
+}
+
+/// Estimated cost of the basket at one place.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoreEstimate {
+    pub place: String,
+    pub cost: f64,
+    pub found: usize,
+    pub missing: Vec<String>,
+}
+
+impl Basket {
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Prices the basket at every place where any of its items was bought, using the
+    /// latest purchase of each item there. `purchases` holds the history of every item,
+    /// oldest first. Places with every item come first, cheapest first.
+    pub fn estimate(&self, purchases: &BTreeMap<String, Vec<Purchase>>) -> Vec<StoreEstimate> {
+        let mut places = purchases
+            .values()
+            .flatten()
+            .map(|x| x.place.trim().to_owned())
+            .collect::<Vec<_>>();
+        places.sort();
+        places.dedup();
+
+        let mut estimates = places
+            .into_iter()
+            .map(|place| {
+                let mut cost = 0.0;
+                let mut found = 0;
+                let mut missing = Vec::new();
+                for (name, quantity) in &self.items {
+                    let latest = purchases
+                        .get(name)
+                        .and_then(|x| x.iter().rev().find(|x| x.place.trim() == place));
+                    match latest {
+                        Some(purchase) => {
+                            cost += purchase.unit_cost() * quantity;
+                            found += 1;
+                        }
+                        None => missing.push(name.clone()),
+                    }
+                }
+                StoreEstimate {
+                    place,
+                    cost,
+                    found,
+                    missing,
+                }
+            })
+            .collect::<Vec<_>>();
+        estimates.sort_by(|a, b| {
+            a.missing
+                .len()
+                .cmp(&b.missing.len())
+                .then(a.cost.total_cmp(&b.cost))
+        });
+        estimates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::purchase;
+
+    #[test]
+    fn test_estimate() {
+        let basket = Basket::from_toml("[items]\nCerveza = 6\n\"Vino Tinto\" = 1").unwrap();
+        let purchases = BTreeMap::from([
+            (
+                "Cerveza".to_owned(),
+                vec![
+                    purchase("2025-01-03", "Oxxo", 20.0, None),
+                    purchase("2025-01-03", "Walmart", 100.0, Some(6.0)),
+                    purchase("2025-01-03", "Oxxo", 22.0, None),
+                ],
+            ),
+            (
+                "Vino Tinto".to_owned(),
+                vec![purchase("2025-01-03", "Walmart", 150.0, None)],
+            ),
+        ]);
+        let estimates = basket.estimate(&purchases);
+        assert_eq!(estimates.len(), 2);
+        assert_eq!(estimates[0].place, "Walmart");
+        assert!((estimates[0].cost - 250.0).abs() < 0.001);
+        assert!(estimates[0].missing.is_empty());
+        assert_eq!(estimates[1].place, "Oxxo");
+        assert!((estimates[1].cost - 132.0).abs() < 0.001);
+        assert_eq!(estimates[1].found, 1);
+        assert_eq!(estimates[1].missing, ["Vino Tinto"]);
+    }
+}
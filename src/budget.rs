@@ -0,0 +1,96 @@
+use crate::report::{period_key, Entry, Period};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Spending limits per `product_type`, read from a TOML file such as:
+///
+/// ```toml
+/// period = "month"
+///
+/// [categories]
+/// Ocio = 1500
+/// Alcohol = 2000
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Budgets {
+    #[serde(default = "default_period")]
+    pub period: Period,
+    pub categories: BTreeMap<String, f64>,
+}
+
+fn default_period() -> Period {
+    Period::Month
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetStatus {
+    pub category: String,
+    pub budget: f64,
+    pub spent: f64,
+}
+
+impl BudgetStatus {
+    pub fn remaining(&self) -> f64 {
+        self.budget - self.spent
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.spent > self.budget
+    }
+}
+
+impl Budgets {
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Sums what was spent in every budgeted category during the given period.
+    pub fn check(&self, entries: &[Entry], period: &str) -> Vec<BudgetStatus> {
+        self.categories
+            .iter()
+            .map(|(category, budget)| BudgetStatus {
+                category: category.clone(),
+                budget: *budget,
+                spent: entries
+                    .iter()
+                    .filter(|x| x.category.trim().eq_ignore_ascii_case(category.trim()))
+                    .filter(|x| period_key(x.date, self.period) == period)
+                    .fold(0.0, |acc, x| acc + x.subtotal + x.tax + x.tip),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::entry;
+
+    #[test]
+    fn test_parse_budgets() {
+        let budgets = Budgets::from_toml("[categories]\nOcio = 1500\nAlcohol = 2000.5\n").unwrap();
+        assert_eq!(budgets.period, Period::Month);
+        assert_eq!(budgets.categories["Alcohol"], 2000.5);
+
+        let budgets = Budgets::from_toml("period = \"year\"\n[categories]\nOcio = 1\n").unwrap();
+        assert_eq!(budgets.period, Period::Year);
+        assert!(Budgets::from_toml("[categories]\nOcio = \"mucho\"\n").is_err());
+    }
+
+    #[test]
+    fn test_check_budgets() {
+        let budgets = Budgets::from_toml("[categories]\nAlcohol = 200\nOcio = 100\n").unwrap();
+        let entries = [
+            entry("2024-12-01", "Alcohol", "walmart", 100.0),
+            entry("2024-12-15", "alcohol", "walmart", 100.0),
+            entry("2024-11-15", "Alcohol", "walmart", 500.0),
+            entry("2024-12-15", "Ocio", "walmart", 50.0),
+        ];
+        let statuses = budgets.check(&entries, "2024-12");
+        assert_eq!(statuses[0].category, "Alcohol");
+        assert!((statuses[0].spent - 232.0).abs() < 0.001);
+        assert!(statuses[0].exceeded());
+        assert!((statuses[1].remaining() - 42.0).abs() < 0.001);
+        assert!(!statuses[1].exceeded());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::product::Product;
+use crate::reader::normalize;
+use crate::tax_engine::{PricingMode, TaxEngine, TaxRate};
+use serde::Deserialize;
+
+/// Smallest similarity, between 0 and 1, for a misspelled name to match an entry.
+const MIN_SIMILARITY: f64 = 0.8;
+
+/// A canonical product, as written in the catalog file:
+///
+/// ```toml
+/// [[product]]
+/// name = "Vino Tinto"
+/// aliases = ["Tinto", "Vino Tinto Casillero"]
+/// category = "Alcohol"
+/// tax_rate = 0.16
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CatalogEntry {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub category: Option<String>,
+    /// Replaces the jurisdiction's general consumption tax rate for this product, e.g. 0 for
+    /// zero-rated food. Other taxes, like IEPS, still apply.
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    product: Vec<CatalogEntry>,
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != *y);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+fn contains_words(name: &str, words: &str) -> bool {
+    let name = name.split(' ').collect::<Vec<_>>();
+    words.split(' ').all(|x| name.contains(&x))
+}
+
+impl CatalogEntry {
+    fn names(&self) -> impl Iterator<Item = String> + '_ {
+        std::iter::once(&self.name)
+            .chain(self.aliases.iter())
+            .map(|x| normalize(x))
+    }
+}
+
+impl Catalog {
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Finds the entry for a raw name: an exact name or alias first, then the longest entry
+    /// whose words all appear in the name, then the most similar spelling.
+    pub fn find(&self, raw_name: &str) -> Option<&CatalogEntry> {
+        let name = normalize(raw_name);
+        if let Some(entry) = self.product.iter().find(|x| x.names().any(|x| x == name)) {
+            return Some(entry);
+        }
+        let by_words = self
+            .product
+            .iter()
+            .flat_map(|entry| entry.names().map(move |x| (entry, x)))
+            .filter(|(_, x)| contains_words(&name, x))
+            .max_by_key(|(_, x)| x.len());
+        if let Some((entry, _)) = by_words {
+            return Some(entry);
+        }
+        let name = name.as_str();
+        self.product
+            .iter()
+            .flat_map(|entry| entry.names().map(move |x| (entry, similarity(name, &x))))
+            .filter(|(_, x)| *x >= MIN_SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entry, _)| entry)
+    }
+
+    /// Renames products to their canonical names and fills in missing categories,
+    /// returning the names that match no entry.
+    pub fn canonicalize(&self, products: &mut [Product]) -> Vec<String> {
+        let mut unmatched = Vec::new();
+        for product in products
+            .iter_mut()
+            .filter(|x| x.product != "Propina" && x.product_type != "Impuestos")
+        {
+            match self.find(&product.product) {
+                Some(entry) => {
+                    product.product = entry.name.clone();
+                    if let (true, Some(category)) =
+                        (product.product_type.trim().is_empty(), &entry.category)
+                    {
+                        product.product_type = category.clone();
+                    }
+                }
+                None => unmatched.push(product.product.trim().to_owned()),
+            }
+        }
+        unmatched
+    }
+
+    pub fn with_tax_rules(&self, engine: Box<dyn TaxEngine>) -> CatalogTaxes {
+        CatalogTaxes {
+            engine,
+            rates: self
+                .product
+                .iter()
+                .filter_map(|x| x.tax_rate.map(|rate| (x.name.clone(), rate)))
+                .collect(),
+        }
+    }
+}
+
+/// Wraps a jurisdiction engine with the tax rates set in the catalog.
+#[derive(Debug)]
+pub struct CatalogTaxes {
+    engine: Box<dyn TaxEngine>,
+    rates: Vec<(String, f64)>,
+}
+
+impl TaxEngine for CatalogTaxes {
+    fn name(&self) -> String {
+        format!("{} with catalog tax rules", self.engine.name())
+    }
+
+    /// The engine's rates with the primary tax charged at the catalog rate, keeping the
+    /// others, like IEPS, and how the engine compounds them.
+    fn rates(&self, product: &Product) -> Vec<TaxRate> {
+        match self.rates.iter().find(|(name, _)| *name == product.product) {
+            Some((_, rate)) => self.engine.rates_with_primary(product, *rate),
+            None => self.engine.rates(product),
+        }
+    }
+
+    fn primary_tax(&self) -> &'static str {
+        self.engine.primary_tax()
+    }
+
+    fn pricing_mode(&self) -> PricingMode {
+        self.engine.pricing_mode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+    use crate::tax_engine::{from_code, MX_IVA};
+
+    const CATALOG: &str = r#"
+[[product]]
+name = "Vino Tinto"
+aliases = ["Tinto"]
+category = "Alcohol"
+
+[[product]]
+name = "Jabón"
+category = "Abarrotes"
+
+[[product]]
+name = "Tortillas"
+category = "Comida"
+tax_rate = 0.0
+"#;
+
+    #[test]
+    fn test_find() {
+        let catalog = Catalog::from_toml(CATALOG).unwrap();
+        let find = |x: &str| catalog.find(x).map(|x| x.name.as_str());
+        assert_eq!(find("vino tinto "), Some("Vino Tinto"));
+        assert_eq!(find("TINTO"), Some("Vino Tinto"));
+        assert_eq!(find("VINO TINTO CASILLERO"), Some("Vino Tinto"));
+        assert_eq!(find("JABON"), Some("Jabón"));
+        assert_eq!(find("Tortilas"), Some("Tortillas"));
+        assert_eq!(find("Rummy"), None);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let catalog = Catalog::from_toml(CATALOG).unwrap();
+        let mut products = read_file(
+            "
+            2024-12-27	VINO TINTO CASILLERO		walmart	 $148.00
+            2024-12-27	jabon	Limpieza	walmart	 $67.00
+            2024-12-27	Rummy	Ocio	walmart	 $185.00
+            2024-12-27	IVA	Impuestos	walmart	 $20.00
+            ",
+        );
+        let unmatched = catalog.canonicalize(&mut products);
+        assert_eq!(products[0].product, "Vino Tinto");
+        assert_eq!(products[0].product_type, "Alcohol");
+        assert_eq!(products[1].product, "Jabón");
+        assert_eq!(products[1].product_type, "Limpieza");
+        assert_eq!(products[3].product, "IVA");
+        assert_eq!(unmatched, ["Rummy"]);
+    }
+
+    #[test]
+    fn test_tax_rules() {
+        let catalog = Catalog::from_toml(CATALOG).unwrap();
+        let engine = catalog.with_tax_rules(from_code("mx").unwrap());
+        let mut products = read_file(
+            "
+            2024-12-27	Tortillas	Comida	walmart	 $20.00
+            2024-12-27	Jabón	Abarrotes	walmart	 $67.00
+            ",
+        );
+        catalog.canonicalize(&mut products);
+        assert_eq!(engine.rate(&products[0]), 0.0);
+        assert_eq!(engine.rates(&products[0])[0].name, "IVA");
+        assert_eq!(engine.rate(&products[1]), MX_IVA);
+    }
+
+    #[test]
+    fn test_tax_rules_keep_other_taxes() {
+        let catalog = Catalog::from_toml(
+            "[[product]]\nname = \"Vino Tinto\"\ncategory = \"Alcohol\"\ntax_rate = 0.08\n",
+        )
+        .unwrap();
+        let engine = catalog.with_tax_rules(from_code("mx-ieps").unwrap());
+        let mut products = read_file("2024-12-27\tVino Tinto\tAlcohol\twalmart\t $148.00\n");
+        catalog.canonicalize(&mut products);
+        let rates = engine.rates(&products[0]);
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].name, "IEPS");
+        assert_eq!(rates[0].rate, 0.265);
+        assert_eq!(rates[1].name, "IVA");
+        assert!((rates[1].rate - 0.08 * 1.265).abs() < 1e-9);
+    }
+}
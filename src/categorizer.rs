@@ -0,0 +1,254 @@
+use crate::product::Product;
+use crate::reader::normalize;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// A rule as written in the rules file. Either `name` (exact, ignoring case and spacing)
+/// or `pattern` (a regular expression, ignoring case) selects the products.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct RuleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub place: Option<String>,
+    pub category: String,
+    /// Replace a `product_type` that is already filled in.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub overwrite: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RulesFile {
+    #[serde(default)]
+    pub rule: Vec<RuleConfig>,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Exact(String),
+    Pattern(Regex),
+}
+
+#[derive(Debug)]
+struct Rule {
+    matcher: Matcher,
+    place: Option<String>,
+    category: String,
+    overwrite: bool,
+}
+
+/// Categorization rules, place specific rules take precedence over general ones.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+/// Lines the invoice handles by name or type, which rules must leave alone.
+fn is_control_line(product: &Product) -> bool {
+    product.product == "Propina" || product.product_type == "Impuestos"
+}
+
+pub fn is_uncategorized(product: &Product) -> bool {
+    product.product_type.trim().is_empty()
+}
+
+impl Rule {
+    fn from_config(config: RuleConfig) -> Result<Self, String> {
+        let matcher = match (config.name, config.pattern) {
+            (Some(name), None) => Matcher::Exact(normalize(&name)),
+            (None, Some(pattern)) => Matcher::Pattern(
+                RegexBuilder::new(&pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|x| format!("invalid pattern {:?}: {}", pattern, x))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "rule for {:?} needs either a name or a pattern",
+                    config.category
+                ))
+            }
+        };
+        Ok(Rule {
+            matcher,
+            place: config.place.map(|x| normalize(&x)),
+            category: config.category,
+            overwrite: config.overwrite,
+        })
+    }
+
+    fn matches(&self, product: &Product) -> bool {
+        if let Some(place) = &self.place {
+            if *place != normalize(&product.place) {
+                return false;
+            }
+        }
+        match &self.matcher {
+            Matcher::Exact(name) => *name == normalize(&product.product),
+            Matcher::Pattern(pattern) => pattern.is_match(product.product.trim()),
+        }
+    }
+}
+
+impl Rules {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let file: RulesFile = toml::from_str(content).map_err(|x| x.to_string())?;
+        let mut rules = file
+            .rule
+            .into_iter()
+            .map(Rule::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Stable, so file order is kept within each group.
+        rules.sort_by_key(|x| x.place.is_none());
+        Ok(Rules { rules })
+    }
+
+    fn find(&self, product: &Product) -> Option<&Rule> {
+        self.rules.iter().find(|x| x.matches(product))
+    }
+
+    /// Fills in or overrides `product_type`, returning the products left uncategorized.
+    pub fn categorize(&self, products: &mut [Product]) -> Vec<String> {
+        for product in products.iter_mut().filter(|x| !is_control_line(x)) {
+            if let Some(rule) = self.find(product) {
+                if rule.overwrite || is_uncategorized(product) {
+                    product.product_type = rule.category.clone();
+                }
+            }
+        }
+        products
+            .iter()
+            .filter(|x| is_uncategorized(x))
+            .map(|x| x.product.trim().to_owned())
+            .collect()
+    }
+}
+
+type CategoryCounts = Vec<(String, usize)>;
+
+/// Proposes an exact name rule for every product, using the category it was given most often.
+pub fn suggest_rules(products: &[(String, String)]) -> RulesFile {
+    let mut counts: Vec<(String, String, CategoryCounts)> = Vec::new();
+    for (name, category) in products {
+        let category = category.trim();
+        if category.is_empty() || category == "Impuestos" || name.trim() == "Propina" {
+            continue;
+        }
+        let key = normalize(name);
+        let index = match counts.iter().position(|(x, _, _)| *x == key) {
+            Some(index) => index,
+            None => {
+                counts.push((key, name.trim().to_owned(), Vec::new()));
+                counts.len() - 1
+            }
+        };
+        let categories = &mut counts[index].2;
+        match categories.iter_mut().find(|(x, _)| x == category) {
+            Some((_, count)) => *count += 1,
+            None => categories.push((category.to_owned(), 1)),
+        }
+    }
+    let rule = counts
+        .into_iter()
+        .filter_map(|(_, name, categories)| {
+            // Ties keep the category seen first.
+            let (category, _) = categories
+                .into_iter()
+                .rev()
+                .max_by_key(|(_, count)| *count)?;
+            Some(RuleConfig {
+                name: Some(name),
+                category,
+                ..Default::default()
+            })
+        })
+        .collect();
+    RulesFile { rule }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+
+    const RULES: &str = r#"
+[[rule]]
+pattern = "^vino"
+category = "Alcohol"
+overwrite = true
+
+[[rule]]
+name = "Jabón"
+category = "Abarrotes"
+
+[[rule]]
+name = "Agua"
+place = "Restaurant"
+category = "Restaurante"
+
+[[rule]]
+name = "Agua"
+category = "Bebida"
+"#;
+
+    #[test]
+    fn test_categorize() {
+        let rules = Rules::from_toml(RULES).unwrap();
+        let mut products = read_file(
+            "
+            2024-12-27	Vino Tinto	Comida	walmart	 $148.00
+            2024-12-27	JABON 	 	walmart	 $67.00
+            2024-12-27	Agua		Restaurant	 $20.00
+            2024-12-27	Agua		walmart	 $10.00
+            2024-12-27	Rummy		walmart	 $185.00
+            2024-12-27	IVA	Impuestos	walmart	 $20.00
+            ",
+        );
+        let uncategorized = rules.categorize(&mut products);
+        let types = products
+            .iter()
+            .map(|x| x.product_type.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "Alcohol",
+                "Abarrotes",
+                "Restaurante",
+                "Bebida",
+                "",
+                "Impuestos"
+            ]
+        );
+        assert_eq!(uncategorized, ["Rummy"]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(Rules::from_toml("[[rule]]\ncategory = \"Ocio\"\n").is_err());
+        assert!(Rules::from_toml("[[rule]]\npattern = \"(\"\ncategory = \"Ocio\"\n").is_err());
+    }
+
+    #[test]
+    fn test_suggest_rules() {
+        let products = [
+            ("Vino Tinto".to_owned(), "Alcohol".to_owned()),
+            ("vino tinto ".to_owned(), "Comida".to_owned()),
+            ("Vino Tinto".to_owned(), "Alcohol".to_owned()),
+            ("Sidra".to_owned(), "".to_owned()),
+            ("IVA".to_owned(), "Impuestos".to_owned()),
+        ];
+        let suggestions = suggest_rules(&products);
+        assert_eq!(suggestions.rule.len(), 1);
+        assert_eq!(suggestions.rule[0].name.as_deref(), Some("Vino Tinto"));
+        assert_eq!(suggestions.rule[0].category, "Alcohol");
+        let toml = toml::to_string(&suggestions).unwrap();
+        assert!(Rules::from_toml(&toml).is_ok());
+    }
+}
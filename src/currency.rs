@@ -0,0 +1,208 @@
+use crate::date::Date;
+use serde::Deserialize;
+
+/// Currency of amounts that only carry a `$`.
+pub const DEFAULT_CURRENCY: &str = "MXN";
+
+/// Symbols that identify a currency, longest first so `US$` wins over a plain `$`.
+const SYMBOLS: [(&str, &str); 5] = [
+    ("US$", "USD"),
+    ("MX$", "MXN"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+];
+
+const CODES: [&str; 7] = ["USD", "EUR", "MXN", "GBP", "JPY", "CAD", "CHF"];
+
+/// Finds the currency named by a symbol or an ISO code in an amount or column.
+pub fn detect(value: &str) -> Option<&'static str> {
+    let upper = value.to_uppercase();
+    SYMBOLS
+        .iter()
+        .find(|(symbol, _)| upper.contains(symbol))
+        .map(|(_, code)| *code)
+        .or_else(|| {
+            upper
+                .split(|x: char| !x.is_ascii_alphabetic())
+                .find_map(|word| CODES.iter().find(|x| **x == word).copied())
+        })
+}
+
+/// Removes currency symbols and codes, leaving the number. Codes may be written apart or
+/// next to it, as in `20USD`.
+pub fn strip(value: &str) -> String {
+    let mut value = value.to_owned();
+    for (symbol, _) in SYMBOLS {
+        value = value.replace(symbol, "");
+    }
+    value
+        .split_whitespace()
+        .map(strip_code)
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Removes a code at either end of a word, unless it is part of a longer word like `CADENA`.
+fn strip_code(word: &str) -> &str {
+    let letter = |c: char| c.is_ascii_alphabetic();
+    for code in CODES {
+        if let Some((head, rest)) = word.split_at_checked(code.len()) {
+            if head.eq_ignore_ascii_case(code) && !rest.starts_with(letter) {
+                return rest;
+            }
+        }
+        if let Some((rest, tail)) = word
+            .len()
+            .checked_sub(code.len())
+            .and_then(|x| word.split_at_checked(x))
+        {
+            if tail.eq_ignore_ascii_case(code) && !rest.ends_with(letter) {
+                return rest;
+            }
+        }
+    }
+    word
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct RateConfig {
+    date: String,
+    currency: String,
+    rate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RatesFile {
+    base: String,
+    #[serde(default)]
+    rate: Vec<RateConfig>,
+}
+
+/// Exchange rates by date, read from a TOML file such as:
+///
+/// ```toml
+/// base = "MXN"
+///
+/// [[rate]]
+/// date = "2024-12-27"
+/// currency = "USD"
+/// rate = 20.30
+/// ```
+///
+/// where `rate` is the value of one unit of `currency` in the base currency.
+#[derive(Debug)]
+pub struct ExchangeRates {
+    base: String,
+    rates: Vec<(Date, String, f64)>,
+}
+
+impl ExchangeRates {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let file: RatesFile = toml::from_str(content).map_err(|x| x.to_string())?;
+        let mut rates = file
+            .rate
+            .into_iter()
+            .map(|x| match Date::parse(&x.date) {
+                Some(date) => Ok((date, x.currency.to_uppercase(), x.rate)),
+                None => Err(format!("invalid date for {} rate: {}", x.currency, x.date)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        rates.sort_by_key(|x| x.0);
+        Ok(ExchangeRates {
+            base: file.base.to_uppercase(),
+            rates,
+        })
+    }
+
+    /// Value of one unit in the base currency, from the latest rate on or before the date,
+    /// or the latest rate known when there is no date.
+    fn to_base(&self, currency: &str, date: Option<Date>) -> Option<f64> {
+        if currency.eq_ignore_ascii_case(&self.base) {
+            return Some(1.0);
+        }
+        self.rates
+            .iter()
+            .rev()
+            .filter(|(_, code, _)| code.eq_ignore_ascii_case(currency))
+            .find(|(x, _, _)| date.is_none_or(|date| *x <= date))
+            .map(|(_, _, rate)| *rate)
+    }
+
+    /// Rate to multiply amounts in `from` by to get them in `to`.
+    pub fn rate(&self, from: &str, to: &str, date: Option<Date>) -> Option<f64> {
+        if from.eq_ignore_ascii_case(to) {
+            return Some(1.0);
+        }
+        Some(self.to_base(from, date)? / self.to_base(to, date)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("US$12.00"), Some("USD"));
+        assert_eq!(detect("12,50 €"), Some("EUR"));
+        assert_eq!(detect(" $12.00 usd"), Some("USD"));
+        assert_eq!(detect("EUR"), Some("EUR"));
+        assert_eq!(detect("$12.00"), None);
+        assert_eq!(detect("Museum"), None);
+        assert_eq!(strip("US$12.00"), "12.00");
+        assert_eq!(strip(" $12.00 USD"), "$12.00");
+        assert_eq!(strip("12.50 €"), "12.50");
+        assert_eq!(strip("1,234.00MXN"), "1,234.00");
+        assert_eq!(strip("20usd"), "20");
+        assert_eq!(strip("USD20"), "20");
+        assert_eq!(strip("CADENA"), "CADENA");
+        assert_eq!(detect("20USD"), Some("USD"));
+    }
+
+    #[test]
+    fn test_rates() {
+        let rates = ExchangeRates::from_toml(
+            r#"
+            base = "MXN"
+
+            [[rate]]
+            date = "2024-12-01"
+            currency = "USD"
+            rate = 20.0
+
+            [[rate]]
+            date = "2024-12-20"
+            currency = "USD"
+            rate = 20.5
+
+            [[rate]]
+            date = "2024-12-01"
+            currency = "EUR"
+            rate = 21.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            rates.rate("USD", "MXN", Date::new(2024, 12, 10)),
+            Some(20.0)
+        );
+        assert_eq!(
+            rates.rate("usd", "MXN", Date::new(2024, 12, 27)),
+            Some(20.5)
+        );
+        assert_eq!(rates.rate("USD", "MXN", None), Some(20.5));
+        assert_eq!(
+            rates.rate("MXN", "USD", Date::new(2024, 12, 10)),
+            Some(0.05)
+        );
+        assert_eq!(
+            rates.rate("EUR", "USD", Date::new(2024, 12, 10)),
+            Some(1.05)
+        );
+        assert_eq!(rates.rate("USD", "MXN", Date::new(2024, 11, 30)), None);
+        assert_eq!(rates.rate("GBP", "MXN", None), None);
+        assert_eq!(rates.rate("GBP", "GBP", None), Some(1.0));
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SPANISH_MONTHS: [&str; 12] = [
+    "enero",
+    "febrero",
+    "marzo",
+    "abril",
+    "mayo",
+    "junio",
+    "julio",
+    "agosto",
+    "septiembre",
+    "octubre",
+    "noviembre",
+    "diciembre",
+];
+
+/// A calendar date as written in the receipts, e.g. `viernes, 27 de diciembre de 2024`,
+/// `2024-12-27` or `27/12/2024`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Date { year, month, day })
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some((year, rest)) = value.split_once('-') {
+            let (month, day) = rest.split_once('-')?;
+            return Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+        }
+        if let Some((day, rest)) = value.split_once('/') {
+            let (month, year) = rest.split_once('/')?;
+            return Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+        }
+        parse_spanish(value)
+    }
+
+    /// Current date in UTC.
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs());
+        Date::from_days(seconds as i64 / 86_400)
+    }
+
+    /// Date from the number of days since 1970-01-01.
+    pub fn from_days(days: i64) -> Self {
+        // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date {
+            year: year as i32,
+            month,
+            day,
+        }
+    }
+
+    /// Number of days since 1970-01-01, the inverse of `from_days`.
+    pub fn to_days(self) -> i64 {
+        // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let shifted_month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// Year and month as `YYYY-MM`, used to group spending per month.
+    pub fn month_key(&self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+
+    pub fn previous_month(&self) -> Date {
+        if self.month == 1 {
+            Date {
+                year: self.year - 1,
+                month: 12,
+                day: 1,
+            }
+        } else {
+            Date {
+                year: self.year,
+                month: self.month - 1,
+                day: 1,
+            }
+        }
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn parse_spanish(value: &str) -> Option<Date> {
+    let value = value.to_lowercase();
+    let value = value.split_once(',').map_or(value.as_str(), |(_, x)| x);
+    let parts = value
+        .split_whitespace()
+        .filter(|x| *x != "de" && *x != "del")
+        .collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return None;
+    }
+    let month = SPANISH_MONTHS
+        .iter()
+        .position(|x| *x == parts[1] || (parts[1] == "setiembre" && *x == "septiembre"))?;
+    Date::new(
+        parts[2].parse().ok()?,
+        month as u32 + 1,
+        parts[0].parse().ok()?,
+    )
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spanish_date() {
+        let date = Date::parse(" viernes, 27 de diciembre de 2024").unwrap();
+        assert_eq!(date, Date::new(2024, 12, 27).unwrap());
+        assert_eq!(date.to_string(), "2024-12-27");
+        assert_eq!(date.month_key(), "2024-12");
+        assert_eq!(Date::parse("1 de Enero de 2025"), Date::new(2025, 1, 1));
+    }
+
+    #[test]
+    fn test_parse_numeric_dates() {
+        assert_eq!(Date::parse("2021-01-01"), Date::new(2021, 1, 1));
+        assert_eq!(Date::parse("29/02/2024"), Date::new(2024, 2, 29));
+        assert_eq!(Date::parse("29/02/2023"), None);
+        assert_eq!(Date::parse("Hoy"), None);
+    }
+
+    #[test]
+    fn test_days_conversion() {
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1).unwrap());
+        assert_eq!(Date::from_days(20_084), Date::new(2024, 12, 27).unwrap());
+        assert_eq!(Date::from_days(19_782), Date::new(2024, 2, 29).unwrap());
+        assert!(Date::today().year >= 2024);
+        assert_eq!(Date::new(2024, 12, 27).unwrap().to_days(), 20_084);
+        assert_eq!(Date::new(1969, 12, 31).unwrap().to_days(), -1);
+        for days in [-800, 0, 59, 19_782, 20_084, 30_000] {
+            assert_eq!(Date::from_days(days).to_days(), days);
+        }
+    }
+
+    #[test]
+    fn test_previous_month() {
+        let date = Date::new(2025, 1, 15).unwrap();
+        assert_eq!(date.previous_month().month_key(), "2024-12");
+        assert_eq!(
+            date.previous_month().previous_month().month_key(),
+            "2024-11"
+        );
+    }
+}
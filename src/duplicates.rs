@@ -0,0 +1,183 @@
+use crate::date::Date;
+use crate::invoice::Invoice;
+use crate::reader::normalize;
+
+/// Largest difference between amounts still considered the same, as a share of the amount,
+/// with a floor of one unit of currency.
+const TOTAL_TOLERANCE: f64 = 0.01;
+/// Share of product lines that must appear in both receipts for them to be similar.
+const LINES_OVERLAP: f64 = 0.8;
+
+/// What identifies a receipt when looking for duplicates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    pub source: String,
+    pub date: String,
+    pub place: String,
+    pub lines: Vec<(String, f64)>,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicateKind {
+    Exact,
+    Similar,
+}
+
+fn normalize_date(value: &str) -> String {
+    Date::parse(value).map_or_else(|| normalize(value), |x| x.to_string())
+}
+
+fn line_tolerance(amount: f64) -> f64 {
+    (amount.abs() * TOTAL_TOLERANCE).max(1.0)
+}
+
+fn cents(value: f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+/// FNV-1a, stable across builds so fingerprints can be stored.
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Receipt {
+    pub fn new(
+        source: &str,
+        date: &str,
+        place: &str,
+        lines: Vec<(String, f64)>,
+        total: f64,
+    ) -> Self {
+        Receipt {
+            source: source.to_owned(),
+            date: normalize_date(date),
+            place: normalize(place),
+            lines: lines
+                .into_iter()
+                .map(|(name, price)| (normalize(&name), price))
+                .collect(),
+            total,
+        }
+    }
+
+    pub fn from_invoice(source: &str, invoice: &Invoice) -> Self {
+        let first = invoice.products().next();
+        Receipt::new(
+            source,
+            first.map_or("", |x| x.date.as_str()),
+            first.map_or("", |x| x.place.as_str()),
+            invoice
+                .products()
+                .map(|x| {
+                    (
+                        x.product.clone(),
+                        x.original_price() * invoice.exchange_rate(),
+                    )
+                })
+                .collect(),
+            invoice.calculate_total() * invoice.exchange_rate(),
+        )
+    }
+
+    /// Hash of date, place, sorted product lines and total, all normalized.
+    pub fn fingerprint(&self) -> String {
+        let mut lines = self
+            .lines
+            .iter()
+            .map(|(name, price)| format!("{}={}", name, cents(*price)))
+            .collect::<Vec<_>>();
+        lines.sort();
+        let key = format!(
+            "{}|{}|{}|{}",
+            self.date,
+            self.place,
+            lines.join(";"),
+            cents(self.total)
+        );
+        format!("{:016x}", fnv1a(&key))
+    }
+
+    /// Same date and place, totals within tolerance and most product lines in common.
+    pub fn is_similar(&self, other: &Receipt) -> bool {
+        if self.date != other.date || self.place != other.place {
+            return false;
+        }
+        let tolerance = line_tolerance(self.total.abs().max(other.total.abs()));
+        if (self.total - other.total).abs() > tolerance {
+            return false;
+        }
+        let mut remaining = other.lines.clone();
+        let common = self
+            .lines
+            .iter()
+            .filter(|(name, price)| {
+                let found = remaining.iter().position(|(x_name, x_price)| {
+                    x_name == name && (x_price - price).abs() <= line_tolerance(*price)
+                });
+                found.map(|i| remaining.remove(i)).is_some()
+            })
+            .count();
+        let largest = self.lines.len().max(other.lines.len());
+        largest == 0 || common as f64 / largest as f64 >= LINES_OVERLAP
+    }
+
+    /// The first known receipt similar to this one. Exact copies are found by their stored
+    /// fingerprint instead.
+    pub fn find_similar<'a>(&self, known: &'a [Receipt]) -> Option<&'a Receipt> {
+        known.iter().find(|x| self.is_similar(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(source: &str, lines: &[(&str, f64)]) -> Receipt {
+        Receipt::new(
+            source,
+            "viernes, 27 de diciembre de 2024",
+            "walmart",
+            lines.iter().map(|(x, y)| (x.to_string(), *y)).collect(),
+            lines.iter().map(|(_, y)| y).sum(),
+        )
+    }
+
+    #[test]
+    fn test_exact_duplicate() {
+        let original = receipt("a.tsv", &[("Vino Tinto", 148.0), ("Sidra", 88.45)]);
+        let copy = Receipt::new(
+            "b.tsv",
+            "2024-12-27",
+            " Walmart ",
+            vec![
+                ("sidra".to_owned(), 88.45),
+                ("VINO  TINTO".to_owned(), 148.0),
+            ],
+            236.45,
+        );
+        assert_eq!(original.fingerprint(), copy.fingerprint());
+    }
+
+    #[test]
+    fn test_similar_duplicate() {
+        let lines = [
+            ("Vino Rosado", 256.0),
+            ("Vino Tinto", 148.0),
+            ("Sidra", 88.45),
+            ("Pasta", 22.5),
+            ("Jabón", 67.0),
+        ];
+        let original = receipt("a.tsv", &lines);
+        let mut typo = lines;
+        typo[2] = ("Sidra", 88.54);
+        let known = [original];
+        let found = receipt("b.tsv", &typo).find_similar(&known).unwrap();
+        assert_eq!(found.source, "a.tsv");
+
+        let other = receipt("c.tsv", &[("Vino Rosado", 256.0), ("Rummy", 185.0)]);
+        assert!(other.find_similar(&known).is_none());
+    }
+}
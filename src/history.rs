@@ -0,0 +1,88 @@
+use serde::Serialize;
+
+/// A stored purchase of a product.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Purchase {
+    pub date: String,
+    pub place: String,
+    pub original_price: f64,
+    /// Price without taxes or tips.
+    pub price: f64,
+    pub tax: f64,
+    pub quantity: Option<f64>,
+}
+
+impl Purchase {
+    pub fn unit_price(&self) -> Option<f64> {
+        self.quantity
+            .filter(|x| *x > 0.0)
+            .map(|x| self.original_price / x)
+    }
+
+    /// Cost of a single unit with taxes but without tips, taking a purchase without
+    /// quantity as a single unit.
+    pub fn unit_cost(&self) -> f64 {
+        let quantity = self.quantity.filter(|x| *x > 0.0).unwrap_or(1.0);
+        (self.price + self.tax) / quantity
+    }
+}
+
+/// A purchase with the price change against the previous one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PricePoint {
+    #[serde(flatten)]
+    pub purchase: Purchase,
+    pub unit_price: Option<f64>,
+    pub change: Option<f64>,
+    /// Whether the price went up more than the threshold.
+    pub increase: bool,
+}
+
+/// Compares the unit price of every purchase with the previous one, taking a purchase
+/// without quantity as a single unit. `threshold` is a fraction, like 0.1.
+pub fn build(purchases: Vec<Purchase>, threshold: f64) -> Vec<PricePoint> {
+    let mut points: Vec<PricePoint> = Vec::new();
+    for purchase in purchases {
+        let unit_price = purchase.unit_price();
+        let after = unit_price.unwrap_or(purchase.original_price);
+        let change = points
+            .last()
+            .map(|x| x.unit_price.unwrap_or(x.purchase.original_price))
+            .filter(|before| *before != 0.0)
+            .map(|before| (after - before) / before);
+        points.push(PricePoint {
+            purchase,
+            unit_price,
+            change,
+            increase: change.is_some_and(|x| x > threshold),
+        });
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::purchase;
+
+    #[test]
+    fn test_build() {
+        let points = build(
+            vec![
+                purchase("2025-01-03", "Oxxo", 20.0, None),
+                purchase("2025-01-10", "Oxxo", 21.0, None),
+                purchase("2025-02-01", "Oxxo", 75.0, Some(3.0)),
+                purchase("2025-03-01", "Oxxo", 56.0, Some(2.0)),
+            ],
+            0.1,
+        );
+        assert_eq!(points[0].change, None);
+        assert!((points[1].change.unwrap() - 0.05).abs() < 1e-9);
+        assert!(!points[1].increase);
+        assert_eq!(points[2].unit_price, Some(25.0));
+        assert!((points[2].change.unwrap() - 25.0 / 21.0 + 1.0).abs() < 1e-9);
+        assert!(points[2].increase);
+        assert!((points[3].change.unwrap() - 0.12).abs() < 1e-9);
+        assert!(points[3].increase);
+    }
+}
@@ -0,0 +1,62 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Encoding of legacy point-of-sale exports, which also covers Latin-1 text.
+const LEGACY_ENCODING: &Encoding = WINDOWS_1252;
+
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding: {}", label))
+}
+
+/// Decodes the bytes with the given encoding, or guesses it: a byte order mark first, then
+/// UTF-8 when the bytes are valid UTF-8, and Windows-1252 otherwise.
+pub fn decode(bytes: &[u8], encoding: Option<&'static Encoding>) -> (String, &'static Encoding) {
+    let encoding = encoding
+        .or_else(|| Encoding::for_bom(bytes).map(|(x, _)| x))
+        .unwrap_or(match std::str::from_utf8(bytes) {
+            Ok(_) => UTF_8,
+            Err(_) => LEGACY_ENCODING,
+        });
+    let (text, encoding, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// Reads a whole input as text, `-` being the standard input.
+pub fn read_input(path: &Path, encoding: Option<&'static Encoding>) -> io::Result<String> {
+    let bytes = if path == Path::new("-") {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(path)?
+    };
+    Ok(decode(&bytes, encoding).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let (text, encoding) = decode("Jabón".as_bytes(), None);
+        assert_eq!(text, "Jabón");
+        assert_eq!(encoding, UTF_8);
+
+        let (text, encoding) = decode(b"Jab\xf3n \x80", None);
+        assert_eq!(text, "Jabón €");
+        assert_eq!(encoding, WINDOWS_1252);
+
+        let (text, _) = decode(b"\xef\xbb\xbfJab\xc3\xb3n", None);
+        assert_eq!(text, "Jabón");
+        let (text, _) = decode(b"\xff\xfeJ\x00\xf3\x00", None);
+        assert_eq!(text, "Jó");
+
+        let latin1 = parse_encoding("latin1").unwrap();
+        assert_eq!(decode("Jabón".as_bytes(), Some(latin1)).0, "JabÃ³n");
+        assert!(parse_encoding("klingon").is_err());
+    }
+}
@@ -1,34 +1,182 @@
+use crate::currency::DEFAULT_CURRENCY;
 use crate::product::{
     calculate_total_from_products, calculate_total_from_products_mut, extract_by_name,
     extract_by_type_mut, Product,
 };
+use crate::rounding::{RoundingPolicy, RoundingScope};
+use crate::store::Store;
+use crate::tax_engine::{Mexico, PricingMode, TaxEngine};
+use serde::Serialize;
 
-const VAT: f64 = 0.16;
+/// How much of the invoice a single product accounts for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub original: f64,
+    pub base: f64,
+    pub tax: f64,
+    pub tip: f64,
+}
+
+impl Allocation {
+    pub fn computed(&self) -> f64 {
+        self.base + self.tax + self.tip
+    }
+
+    pub fn delta(&self) -> f64 {
+        self.computed() - self.original
+    }
+}
 
 #[derive(Debug)]
 pub struct Invoice<'a> {
     products: Vec<&'a mut Product>,
     tips: Option<Product>,
     taxes: Option<Vec<Product>>,
+    engine: Box<dyn TaxEngine>,
+    pricing_mode: PricingMode,
+    legacy_vat_math: bool,
+    rounding: Option<RoundingPolicy>,
+    rounding_adjustment: f64,
+    store: Option<String>,
+    store_tips: Option<f64>,
+    currency: String,
+    exchange: Option<(String, f64)>,
 }
 
 impl<'a> Invoice<'a> {
     pub fn new(raw_products: Vec<&'a mut Product>) -> Self {
         let mut products = raw_products;
+        let currency = products
+            .iter()
+            .find_map(|x| x.currency.clone())
+            .unwrap_or_else(|| DEFAULT_CURRENCY.to_owned());
         let tips = extract_by_name(&mut products, "Propina").and_then(|mut x| x.pop());
         let taxes = extract_by_type_mut(&mut products, "Impuestos");
         Invoice {
             products,
             tips,
             taxes,
+            engine: Box::new(Mexico::default()),
+            pricing_mode: PricingMode::Inclusive,
+            legacy_vat_math: false,
+            rounding: None,
+            rounding_adjustment: 0.0,
+            store: None,
+            store_tips: None,
+            currency,
+            exchange: None,
+        }
+    }
+
+    /// Sets the tax engine, adopting its pricing mode.
+    pub fn with_engine(mut self, engine: Box<dyn TaxEngine>) -> Self {
+        self.pricing_mode = engine.pricing_mode();
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_pricing_mode(mut self, pricing_mode: PricingMode) -> Self {
+        self.pricing_mode = pricing_mode;
+        self
+    }
+
+    /// Extracts VAT as `price * (1 - rate)` like older versions did, to reproduce past reports.
+    pub fn with_legacy_vat_math(mut self, legacy_vat_math: bool) -> Self {
+        self.legacy_vat_math = legacy_vat_math;
+        self
+    }
+
+    /// Applies the defaults of the store: its pricing mode and the tip its receipts leave
+    /// out. Set the engine first, since it resets the pricing mode.
+    pub fn with_store(mut self, store: &Store) -> Self {
+        if let Some(pricing_mode) = store.pricing_mode() {
+            self.pricing_mode = pricing_mode;
         }
+        self.store = Some(store.name.clone());
+        self.store_tips = store.tip_rate();
+        self
+    }
+
+    /// Converts the totals into another currency, `rate` being the value of one unit of the
+    /// invoice currency in it.
+    pub fn with_exchange(mut self, currency: &str, rate: f64) -> Self {
+        self.exchange = Some((currency.to_owned(), rate));
+        self
+    }
+
+    pub fn with_rounding(mut self, rounding: RoundingPolicy) -> Self {
+        self.rounding = Some(rounding);
+        self
     }
 
     pub fn calculate_taxes(&mut self) {
-        match self.taxes {
+        if let (None, None, Some(tips)) = (&self.tips, &self.taxes, self.store_tips) {
+            self.tips_from_products(tips);
+        }
+        let exact_taxes = match self.taxes {
             None => self.calculate_taxes_from_products(),
-            Some(_) => self.fix_prices_from_taxes(),
+            Some(_) => {
+                self.fix_prices_from_taxes();
+                self.total_taxes()
+            }
+        };
+        if let Some(rounding) = self.rounding {
+            self.apply_rounding(rounding, exact_taxes);
+        }
+    }
+
+    /// Rounds product lines and tips, and keeps whatever cents the rounding loses or gains
+    /// against the exact total as an explicit adjustment.
+    fn apply_rounding(&mut self, rounding: RoundingPolicy, exact_taxes: f64) {
+        let exact_total = self.total_products() + self.total_tips() + exact_taxes;
+        if rounding.scope == RoundingScope::Line {
+            self.products.iter_mut().for_each(|x| {
+                let price = x.price.unwrap_or(0.0);
+                let rounded = rounding.round(price);
+                if rounded != price {
+                    let reason = format!("{:?} to {} decimals", rounding.mode, rounding.precision);
+                    x.adjust_price("round line", rounded, reason);
+                }
+            });
+        }
+        if let Some(tips) = self.tips.as_mut() {
+            tips.price = tips.price.map(|price| rounding.round(price));
         }
+        let rounded_total =
+            rounding.round(self.total_products()) + self.total_tips() + self.total_taxes();
+        self.rounding_adjustment = rounding.round(rounding.round(exact_total) - rounded_total);
+    }
+
+    pub fn rounding_adjustment(&self) -> f64 {
+        self.rounding_adjustment
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Rate from the invoice currency into the reporting currency, 1 when not converting.
+    pub fn exchange_rate(&self) -> f64 {
+        self.exchange.as_ref().map_or(1.0, |(_, rate)| *rate)
+    }
+
+    /// Currency the reports and exports use: the reporting currency when converting.
+    pub fn report_currency(&self) -> &str {
+        self.exchange
+            .as_ref()
+            .map_or(self.currency.as_str(), |(currency, _)| currency.as_str())
+    }
+
+    /// The totals in the reporting currency, when one was set.
+    pub fn conversion(&self) -> Option<Conversion> {
+        self.exchange.as_ref().map(|(currency, rate)| Conversion {
+            currency: currency.clone(),
+            rate: *rate,
+            products: self.total_products() * rate,
+            tips: self.total_tips() * rate,
+            taxes: self.total_taxes() * rate,
+            total: self.calculate_total() * rate,
+        })
     }
 
     fn fix_prices_from_taxes(&mut self) {
@@ -46,7 +194,12 @@ impl<'a> Invoice<'a> {
         let estimated_taxes = self.products.iter_mut().fold(0.0, |acc, x| {
             let price = x.price.unwrap_or(0.0);
             let price = price * (1.0 - estimated_ratio);
-            x.price = Some(price);
+            let reason = format!(
+                "receipt taxes ${:0.2} are {:0.2}% of the products",
+                total_taxes,
+                estimated_ratio * 100.0
+            );
+            x.adjust_price("estimate taxes", price, reason);
             acc + price
         });
 
@@ -75,7 +228,15 @@ impl<'a> Invoice<'a> {
         products_to_fix.iter().for_each(|i| {
             let product = &mut self.products[*i];
             let price = product.price.unwrap_or(0.0) + remaining_taxes;
-            product.price = Some(price);
+            if remaining_taxes.abs() < 0.005 {
+                product.price = Some(price);
+                return;
+            }
+            let reason = format!(
+                "share of ${:0.2} left to reconcile with the receipt total",
+                remaining_taxes * products_to_fix_quantity
+            );
+            product.adjust_price("reconcile taxes", price, reason);
         });
     }
 
@@ -86,80 +247,327 @@ impl<'a> Invoice<'a> {
     pub fn total_taxes(&self) -> f64 {
         self.taxes
             .as_ref()
-            .map_or(0.0, |x| calculate_total_from_products(&x))
+            .map_or(0.0, |x| calculate_total_from_products(x))
     }
 
     pub fn total_products(&self) -> f64 {
         calculate_total_from_products_mut(&self.products)
     }
 
+    pub fn products(&self) -> impl Iterator<Item = &Product> {
+        self.products.iter().map(|x| &**x)
+    }
+
+    pub fn tax_lines(&self) -> &[Product] {
+        self.taxes.as_deref().unwrap_or(&[])
+    }
+
     pub fn tips_from_products(&mut self, tips_percentage: f64) {
-        let total_consumed = self.total_products();
-        let total_percentages = tips_percentage + VAT;
-        let original_consumed = total_consumed / (1.0 + total_percentages);
-        let tips = original_consumed * tips_percentage;
-        self.products.iter_mut().for_each(|x| {
-            let price = x.price.unwrap_or(0.0);
-            let price = price / (1.0 + total_percentages) * (1.0 + VAT);
-            x.price = Some(price);
-        });
-        self.taxes = Some(vec![Product::create_product_from_product(
-            &self.products[0],
-            "IVA",
-            "Impuestos",
-            Some(original_consumed * VAT),
-        )]);
+        let included = self.pricing_mode == PricingMode::Inclusive;
+        let mut tips = 0.0;
+        let mut taxes = TaxAccumulator::new(self.rounding);
+        for product in self.products.iter_mut() {
+            let price = product.price.unwrap_or(0.0);
+            let rate = self.engine.rate(product);
+            let original = if included {
+                price / (1.0 + tips_percentage + rate)
+            } else {
+                price / (1.0 + tips_percentage)
+            };
+            tips += original * tips_percentage;
+            self.engine
+                .rates(product)
+                .iter()
+                .for_each(|x| taxes.add(x.name, x.rate, original));
+            let reason = format!(
+                "removed {:0.2}% tip, kept {:0.2}% taxes",
+                tips_percentage * 100.0,
+                rate * 100.0
+            );
+            product.adjust_price("remove tips", original * (1.0 + rate), reason);
+        }
+        let Some(first) = self.products.first() else {
+            return;
+        };
+        self.taxes = Some(taxes.into_products(first));
         self.tips = Some(Product::create_product_from_product(
-            &self.products[0],
+            first,
             "Propina",
-            self.products[0].product_type.as_str(),
+            first.product_type.as_str(),
             Some(tips),
         ));
     }
 
-    fn calculate_taxes_from_products(&mut self) {
-        let total = self.remove_vat_from_products();
-        let taxes = Product::create_product_from_product(
-            &self.products[0],
-            "IVA",
-            "Impuestos",
-            Some(total * VAT),
-        );
-        self.taxes = Some(vec![taxes]);
+    /// Returns the taxes before any rounding.
+    fn calculate_taxes_from_products(&mut self) -> f64 {
+        let taxes = match self.pricing_mode {
+            PricingMode::Inclusive => self.remove_vat_from_products(),
+            PricingMode::Exclusive => self.add_taxes_to_products(),
+        };
+        let exact_taxes = taxes.total();
+        self.taxes = self.products.first().map(|x| taxes.into_products(x));
+        exact_taxes
     }
 
     pub fn calculate_total(&self) -> f64 {
-        let total = self.total_products();
+        let total = self
+            .rounding
+            .map_or(self.total_products(), |x| x.round(self.total_products()));
         let tips = self.total_tips();
         let taxes = self.total_taxes();
-        total + tips + taxes
+        total + tips + taxes + self.rounding_adjustment
     }
 
-    fn remove_vat_from_products(&mut self) -> f64 {
-        self.products.iter_mut().fold(0.0, |acc, x| {
-            let price = x.price.unwrap_or(0.0);
-            x.price = Some(price * (1.0 - VAT));
-            acc + price
-        })
+    fn remove_vat_from_products(&mut self) -> TaxAccumulator {
+        let mut taxes = TaxAccumulator::new(self.rounding);
+        for product in self.products.iter_mut() {
+            let price = product.price.unwrap_or(0.0);
+            let rate = self.engine.rate(product);
+            let (base, taxable, reason) = if self.legacy_vat_math {
+                let reason = format!("price * (1 - {:0.4}), legacy math", rate);
+                (price * (1.0 - rate), price, reason)
+            } else {
+                let reason = format!("price / (1 + {:0.4})", rate);
+                (price / (1.0 + rate), price / (1.0 + rate), reason)
+            };
+            self.engine
+                .rates(product)
+                .iter()
+                .for_each(|x| taxes.add(x.name, x.rate, taxable));
+            product.adjust_price("remove VAT", base, reason);
+        }
+        taxes
+    }
+
+    fn add_taxes_to_products(&self) -> TaxAccumulator {
+        let mut taxes = TaxAccumulator::new(self.rounding);
+        for product in self.products.iter() {
+            let price = product.price.unwrap_or(0.0);
+            self.engine
+                .rates(product)
+                .iter()
+                .for_each(|x| taxes.add(x.name, x.rate, price));
+        }
+        taxes
+    }
+
+    pub fn print_header(&self) {
+        println!("Tax engine: {}", self.engine.name());
+        if let Some(store) = &self.store {
+            println!("Store: {}", store);
+        }
+        println!("Pricing: {}", self.pricing_mode);
+        println!("Currency: {}", self.currency);
+        if let Some((currency, rate)) = &self.exchange {
+            println!("Exchange: 1 {} = {:0.4} {}", self.currency, rate, currency);
+        }
+        if let Some(tips) = self.store_tips {
+            println!("Store tips: {:0.2}%", tips * 100.0);
+        }
+        if self.legacy_vat_math {
+            println!("VAT math: legacy, price * (1 - rate)");
+        }
+        if let Some(rounding) = self.rounding {
+            println!(
+                "Rounding: {:?} {:?}, {} decimals",
+                rounding.scope, rounding.mode, rounding.precision
+            );
+        }
+    }
+
+    /// Splits taxes and tips among products. Taxes follow each product's rate so lines
+    /// computed by the engine get their own tax back; tips follow the pre-tax price.
+    pub fn allocations(&self) -> Vec<Allocation> {
+        let bases = self
+            .products
+            .iter()
+            .map(|x| x.price.unwrap_or(0.0))
+            .collect::<Vec<_>>();
+        let tax_weights = self
+            .products
+            .iter()
+            .zip(bases.iter())
+            .map(|(x, base)| base * self.engine.rate(x))
+            .collect::<Vec<_>>();
+        let total_bases: f64 = bases.iter().sum();
+        let total_tax_weights: f64 = tax_weights.iter().sum();
+        let (tax_weights, total_tax_weights) = if total_tax_weights == 0.0 {
+            (bases.clone(), total_bases)
+        } else {
+            (tax_weights, total_tax_weights)
+        };
+        let share = |weight: f64, total: f64, amount: f64| {
+            if total == 0.0 {
+                0.0
+            } else {
+                amount * weight / total
+            }
+        };
+        self.products
+            .iter()
+            .enumerate()
+            .map(|(i, x)| Allocation {
+                original: x.original_price(),
+                base: bases[i],
+                tax: share(tax_weights[i], total_tax_weights, self.total_taxes()),
+                tip: share(bases[i], total_bases, self.total_tips()),
+            })
+            .collect()
+    }
+
+    /// Prints original and computed prices side by side, checking that the columns add up
+    /// to the invoice total.
+    pub fn show_comparison(&self) {
+        let allocations = self.allocations();
+        let name_width = self
+            .products
+            .iter()
+            .map(|x| x.product.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Product".len());
+        let row = |name: &str, values: [f64; 5]| {
+            println!(
+                "{:<name_width$} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+                name,
+                without_negative_zero(values[0]),
+                without_negative_zero(values[1]),
+                without_negative_zero(values[2]),
+                without_negative_zero(values[3]),
+                without_negative_zero(values[4])
+            );
+        };
+        println!(
+            "{:<name_width$} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Product", "Original", "Base", "Tax", "Tip", "Delta"
+        );
+        println!("{}", "-".repeat(name_width + 55));
+        self.products
+            .iter()
+            .zip(allocations.iter())
+            .for_each(|(product, x)| {
+                row(
+                    &product.product,
+                    [x.original, x.base, x.tax, x.tip, x.delta()],
+                );
+            });
+        let sum = |f: fn(&Allocation) -> f64| allocations.iter().map(f).sum::<f64>();
+        let total_original = sum(|x| x.original);
+        let total_delta = sum(|x| x.delta());
+        println!("{}", "-".repeat(name_width + 55));
+        row(
+            "Total",
+            [
+                total_original,
+                sum(|x| x.base),
+                sum(|x| x.tax),
+                sum(|x| x.tip),
+                total_delta,
+            ],
+        );
+        let added = total_delta + self.rounding_adjustment();
+        let difference = total_original + added - self.calculate_total();
+        println!(
+            "Originals ${:0.2} + added ${:0.2} = invoice total ${:0.2}: {}",
+            total_original,
+            without_negative_zero(added),
+            self.calculate_total(),
+            if difference.abs() < 0.005 {
+                "reconciled".to_owned()
+            } else {
+                format!("MISMATCH by ${:0.2}", difference)
+            }
+        );
+    }
+
+    /// Prints how every product price was derived from the receipt.
+    pub fn explain(&self) {
+        println!("Price adjustments:");
+        self.products.iter().for_each(|x| x.explain());
     }
 
     pub fn show_invoice(&self, show_all: bool) {
         if show_all {
             self.products.iter().for_each(|x| x.show_all());
-            self.tips.as_ref().map(|x| x.show_all());
-            self.taxes
-                .as_ref()
-                .map(|x| x.iter().for_each(|y| y.show_all()));
         } else {
             self.products.iter().for_each(|x| x.show());
-            self.tips.as_ref().map(|x| x.show_all());
-            self.taxes
-                .as_ref()
-                .map(|x| x.iter().for_each(|y| y.show_all()));
+        }
+        if let Some(tips) = &self.tips {
+            tips.show_all();
+        }
+        if let Some(taxes) = &self.taxes {
+            taxes.iter().for_each(|x| x.show_all());
+        }
+    }
+
+    /// Groups products by `product_type`, in order of appearance or by amount.
+    pub fn category_breakdown(&self, sort_by_amount: bool) -> Vec<CategoryTotal> {
+        let total = self.calculate_total();
+        let mut categories: Vec<CategoryTotal> = Vec::new();
+        for (product, allocation) in self.products.iter().zip(self.allocations()) {
+            let index = match categories
+                .iter()
+                .position(|x| x.category == product.product_type)
+            {
+                Some(index) => index,
+                None => {
+                    categories.push(CategoryTotal {
+                        category: product.product_type.clone(),
+                        subtotal: 0.0,
+                        tax: 0.0,
+                        tip: 0.0,
+                        share: 0.0,
+                    });
+                    categories.len() - 1
+                }
+            };
+            let category = &mut categories[index];
+            category.subtotal += allocation.base;
+            category.tax += allocation.tax;
+            category.tip += allocation.tip;
+        }
+        categories.iter_mut().for_each(|x| {
+            x.share = if total == 0.0 { 0.0 } else { x.total() / total };
+        });
+        if sort_by_amount {
+            categories.sort_by(|a, b| b.total().total_cmp(&a.total()));
+        }
+        categories
+    }
+
+    pub fn resume(&self, sort_categories: bool) -> Resume {
+        Resume {
+            engine: self.engine.name(),
+            pricing: self.pricing_mode.to_string(),
+            products: self.total_products(),
+            tips: self.total_tips(),
+            taxes: self.total_taxes(),
+            rounding: self.rounding_adjustment(),
+            total: self.calculate_total(),
+            categories: self.category_breakdown(sort_categories),
+            currency: self.currency.clone(),
+            conversion: self.conversion(),
         }
     }
 
-    pub fn print_resume(&self) {
+    /// Everything `show_invoice` and `print_resume` show, for the document formats.
+    pub fn report(&self, sort_categories: bool) -> InvoiceReport {
+        let line = |x: &Product| InvoiceLine {
+            date: x.date.trim().to_owned(),
+            product: x.product.trim().to_owned(),
+            category: x.product_type.trim().to_owned(),
+            place: x.place.trim().to_owned(),
+            original: x.original_price(),
+            price: x.price.unwrap_or(0.0),
+        };
+        InvoiceReport {
+            lines: self.products().map(line).collect(),
+            taxes: self.tax_lines().iter().map(line).collect(),
+            resume: self.resume(sort_categories),
+        }
+    }
+
+    pub fn print_resume(&self, sort_categories: bool) {
         let products_string = format!("Products: ${:0.2}", self.total_products());
         let tips_string = if self.total_tips() > 0.0 {
             format!("Tips: ${:0.2}", self.total_tips())
@@ -167,9 +575,48 @@ impl<'a> Invoice<'a> {
             "".to_owned()
         };
         let taxes_string = format!("Taxes: ${:0.2}", self.total_taxes());
+        let rounding_string = if self.rounding_adjustment() != 0.0 {
+            format!("Rounding: ${:0.2}", self.rounding_adjustment())
+        } else {
+            "".to_owned()
+        };
         let total_string = format!("Total: ${:0.2}", self.calculate_total());
-        let totals = vec![&products_string, &tips_string, &taxes_string, &total_string];
-        let largest_string = find_largest_string(&totals).max(36);
+        let converted_string = self.conversion().map_or("".to_owned(), |x| {
+            format!(
+                "{} ${:0.2} in {}: ${:0.2}",
+                self.currency,
+                self.calculate_total(),
+                x.currency,
+                x.total
+            )
+        });
+        let totals = vec![
+            &products_string,
+            &tips_string,
+            &taxes_string,
+            &rounding_string,
+            &total_string,
+            &converted_string,
+        ];
+        let categories = self
+            .category_breakdown(sort_categories)
+            .iter()
+            .map(|x| {
+                format!(
+                    "{}: ${:0.2} + ${:0.2} tax ({:0.1}%)",
+                    x.category,
+                    x.subtotal,
+                    x.tax,
+                    x.share * 100.0
+                )
+            })
+            .collect::<Vec<_>>();
+        let all_strings = totals
+            .iter()
+            .copied()
+            .chain(categories.iter())
+            .collect::<Vec<_>>();
+        let largest_string = find_largest_string(&all_strings).max(36);
         let header = "=".repeat(largest_string);
         println!("\n{}", header);
         for x in totals {
@@ -179,30 +626,233 @@ impl<'a> Invoice<'a> {
             if x.contains("Total") {
                 println!("{}", "-".repeat(largest_string));
             }
-            let to_fill = largest_string - x.len();
-            let left_fill = to_fill / 2 - 1;
-            let right_fill = to_fill - left_fill - 2;
-            println!("|{}{}{}|", " ".repeat(left_fill), x, " ".repeat(right_fill));
+            print_boxed_line(x, largest_string);
+        }
+        if !categories.is_empty() {
+            println!("{}", "-".repeat(largest_string));
+            categories
+                .iter()
+                .for_each(|x| print_boxed_line(x, largest_string));
         }
         println!("{}", header);
     }
 }
 
-fn find_largest_string(strings: &Vec<&String>) -> usize {
-    strings.iter().fold(0, |acc, x| acc.max(x.len())) + 4
+/// Amounts of a single `product_type` within the invoice.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub tip: f64,
+    pub share: f64,
+}
+
+impl CategoryTotal {
+    pub fn total(&self) -> f64 {
+        self.subtotal + self.tax + self.tip
+    }
+}
+
+/// A product or tax line, with the price read from the receipt and the computed one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoiceLine {
+    pub date: String,
+    pub product: String,
+    pub category: String,
+    pub place: String,
+    pub original: f64,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceReport {
+    pub lines: Vec<InvoiceLine>,
+    pub taxes: Vec<InvoiceLine>,
+    pub resume: Resume,
+}
+
+/// The figures shown by `print_resume`, for the machine readable formats.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Resume {
+    pub engine: String,
+    pub pricing: String,
+    pub products: f64,
+    pub tips: f64,
+    pub taxes: f64,
+    pub rounding: f64,
+    pub total: f64,
+    pub categories: Vec<CategoryTotal>,
+    pub currency: String,
+    pub conversion: Option<Conversion>,
+}
+
+/// Invoice totals converted into the reporting currency.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Conversion {
+    pub currency: String,
+    pub rate: f64,
+    pub products: f64,
+    pub tips: f64,
+    pub taxes: f64,
+    pub total: f64,
+}
+
+/// Sums the taxable amounts per tax and rate so every tax is computed over its whole base,
+/// or over each line when the rounding policy rounds per line.
+#[derive(Debug)]
+struct TaxAccumulator {
+    rounding: Option<RoundingPolicy>,
+    bases: Vec<(&'static str, f64, f64, f64)>,
+}
+
+impl TaxAccumulator {
+    fn new(rounding: Option<RoundingPolicy>) -> Self {
+        TaxAccumulator {
+            rounding,
+            bases: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &'static str, rate: f64, amount: f64) {
+        let line_tax = self
+            .rounding
+            .map_or(amount * rate, |x| x.round_line(amount * rate));
+        match self
+            .bases
+            .iter_mut()
+            .find(|(x_name, x_rate, _, _)| *x_name == name && *x_rate == rate)
+        {
+            Some((_, _, base, line_taxes)) => {
+                *base += amount;
+                *line_taxes += line_tax;
+            }
+            None => self.bases.push((name, rate, amount, line_tax)),
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.bases
+            .iter()
+            .map(|(_, rate, base, _)| base * rate)
+            .sum()
+    }
+
+    fn into_products(self, template: &Product) -> Vec<Product> {
+        let mut products: Vec<Product> = Vec::new();
+        for (name, rate, base, line_taxes) in self.bases {
+            let amount = match self.rounding {
+                Some(RoundingPolicy {
+                    scope: RoundingScope::Line,
+                    ..
+                }) => line_taxes,
+                _ => base * rate,
+            };
+            match products.iter_mut().find(|x| x.product == name) {
+                Some(product) => product.price = Some(product.price.unwrap_or(0.0) + amount),
+                None => products.push(Product::create_product_from_product(
+                    template,
+                    name,
+                    "Impuestos",
+                    Some(amount),
+                )),
+            }
+        }
+        if let Some(rounding) = self.rounding {
+            products.iter_mut().for_each(|x| {
+                x.price = x.price.map(|price| rounding.round(price));
+            });
+        }
+        products
+    }
+}
+
+/// Keeps float noise like -0.0000001 from printing as -0.00.
+fn without_negative_zero(value: f64) -> f64 {
+    if value.abs() < 0.005 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn print_boxed_line(line: &str, width: usize) {
+    let to_fill = width - line.chars().count();
+    let left_fill = to_fill / 2 - 1;
+    let right_fill = to_fill - left_fill - 2;
+    println!(
+        "|{}{}{}|",
+        " ".repeat(left_fill),
+        line,
+        " ".repeat(right_fill)
+    );
+}
+
+fn find_largest_string(strings: &[&String]) -> usize {
+    strings.iter().fold(0, |acc, x| acc.max(x.chars().count())) + 4
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::invoice::{Invoice, VAT};
+    use crate::invoice::Invoice;
     use crate::product::Product;
     use crate::reader::read_file;
+    use crate::rounding::{RoundingMode, RoundingPolicy, RoundingScope};
+    use crate::store::StoreRegistry;
+    use crate::tax_engine::{from_code, PricingMode, MX_IVA as VAT};
 
     fn round_to_two_decimals(value: f64) -> f64 {
         (value * 100.0).round() / 100.0
     }
 
+    fn single_product(price: f64) -> Product {
+        Product {
+            date: "2021-01-01".to_owned(),
+            product: "Cerveza".to_owned(),
+            product_type: "Bebida".to_owned(),
+            place: "Bar".to_owned(),
+            price: Some(price),
+            quantity: None,
+            currency: None,
+            adjustments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_vat_extraction_from_116() {
+        let mut products = [single_product(116.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products);
+        invoice.calculate_taxes();
+        assert_eq!(round_to_two_decimals(invoice.total_products()), 100.0);
+        assert_eq!(round_to_two_decimals(invoice.total_taxes()), 16.0);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 116.0);
+    }
+
+    #[test]
+    fn test_vat_added_to_100() {
+        let mut products = [single_product(100.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_pricing_mode(PricingMode::Exclusive);
+        invoice.calculate_taxes();
+        assert_eq!(round_to_two_decimals(invoice.total_products()), 100.0);
+        assert_eq!(round_to_two_decimals(invoice.total_taxes()), 16.0);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 116.0);
+    }
+
+    #[test]
+    fn test_legacy_vat_math() {
+        let mut products = [single_product(116.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_legacy_vat_math(true);
+        invoice.calculate_taxes();
+        assert_eq!(round_to_two_decimals(invoice.total_products()), 97.44);
+        assert_eq!(round_to_two_decimals(invoice.total_taxes()), 18.56);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 116.0);
+    }
+
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_new_invoice() {
         let mut products = vec![
             Product {
@@ -211,6 +861,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -218,6 +871,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -225,6 +881,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -232,6 +891,9 @@ mod tests {
                 product_type: "Propina".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -239,6 +901,9 @@ mod tests {
                 product_type: "Impuestos".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
         ];
         let products = products.iter_mut().collect::<Vec<_>>();
@@ -249,6 +914,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_calculate_taxes_from_restaurant() {
         let mut products = vec![
             Product {
@@ -257,6 +923,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -264,6 +933,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -271,6 +943,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -278,6 +953,9 @@ mod tests {
                 product_type: "Propina".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
         ];
         let products = products.iter_mut().collect::<Vec<_>>();
@@ -287,10 +965,10 @@ mod tests {
         let total_products = invoice.total_products();
         let total_tips = invoice.total_tips();
         let total_taxes = invoice.total_taxes();
-        assert_eq!(total, 8.0);
-        assert_eq!(total_products, 6.0 * (1.0 - VAT));
+        assert!((total - 8.0).abs() < 0.001);
+        assert!((total_products - 6.0 / (1.0 + VAT)).abs() < 0.001);
         assert_eq!(total_tips, 2.0);
-        assert_eq!(total_taxes, 6.0 * VAT);
+        assert!((total_taxes - 6.0 / (1.0 + VAT) * VAT).abs() < 0.001);
     }
 
     #[test]
@@ -321,6 +999,65 @@ mod tests {
         assert!((total_taxes - 157.63).abs() < 0.001);
     }
 
+    #[test]
+    fn test_conversion() {
+        let mut products = read_file("2024-12-27\tBeer\tBebida\tBar\tUS$10.00");
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products)
+            .with_engine(from_code("us-ca").unwrap())
+            .with_exchange("MXN", 20.0);
+        invoice.calculate_taxes();
+        assert_eq!(invoice.currency(), "USD");
+        let resume = invoice.resume(false);
+        let conversion = resume.conversion.unwrap();
+        assert_eq!(conversion.currency, "MXN");
+        assert!((resume.total - 10.725).abs() < 0.001);
+        assert!((conversion.products - 200.0).abs() < 0.001);
+        assert!((conversion.total - 214.5).abs() < 0.001);
+
+        let mut products = [single_product(116.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let invoice = Invoice::new(products);
+        assert_eq!(invoice.currency(), "MXN");
+        assert!(invoice.conversion().is_none());
+    }
+
+    #[test]
+    fn test_store_defaults() {
+        let stores = StoreRegistry::from_toml(
+            "
+            [[store]]
+            name = \"Cantina\"
+            tips = true
+            tip_percentage = 10
+
+            [[store]]
+            name = \"Outlet\"
+            prices_include_tax = false
+            ",
+        )
+        .unwrap();
+
+        let mut products = read_file(
+            "
+            viernes, 27 de diciembre de 2024	Torta	Restaurante	Cantina	 $400.00
+            viernes, 27 de diciembre de 2024	Vino Tinto	Restaurante	Cantina	 $253.00
+            ",
+        );
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_store(stores.find("cantina").unwrap());
+        invoice.calculate_taxes();
+        assert_eq!(round_to_two_decimals(invoice.total_tips()), 51.83);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 653.0);
+
+        let mut products = [single_product(100.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_store(stores.find("Outlet").unwrap());
+        invoice.calculate_taxes();
+        assert_eq!(round_to_two_decimals(invoice.total_tips()), 0.0);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 116.0);
+    }
+
     #[test]
     fn test_tips_and_taxes_calculation() {
         let raw_invoice = "
@@ -346,6 +1083,15 @@ mod tests {
         assert_eq!(total, 653.0);
     }
 
+    #[test]
+    fn test_empty_invoice() {
+        let mut invoice = Invoice::new(Vec::new());
+        invoice.tips_from_products(0.1);
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_taxes(), 0.0);
+        assert_eq!(invoice.calculate_total(), 0.0);
+    }
+
     #[test]
     fn test_taxes_calculation_from_bill_no_taxes() {
         let raw_invoice = "
@@ -358,13 +1104,13 @@ mod tests {
         let mut invoice = Invoice::new(products);
         invoice.calculate_taxes();
         let total = invoice.calculate_total();
-        assert_eq!(total, 404.0);
+        assert!((total - 404.0).abs() < 0.001);
         let total_products = invoice.total_products();
         let total_tips = invoice.total_tips();
         let total_taxes = invoice.total_taxes();
-        assert_eq!(total_products, 404.0 * (1.0 - VAT));
+        assert!((total_products - 404.0 / (1.0 + VAT)).abs() < 0.001);
         assert_eq!(total_tips, 0.0);
-        assert_eq!(total_taxes, 404.0 * VAT);
+        assert!((total_taxes - 404.0 / (1.0 + VAT) * VAT).abs() < 0.001);
     }
 
     #[test]
@@ -389,4 +1135,231 @@ mod tests {
         assert_eq!(total_tips, 30.0);
         assert!((total_taxes - 30.63).abs() < 0.001);
     }
+
+    #[test]
+    fn test_taxes_calculation_with_spanish_engine() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Vino Tinto	Alcohol	Mercadona	 $121.00
+        viernes, 27 de diciembre de 2024	Pan	Pan	Mercadona	 $10.40
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_engine(from_code("es").unwrap());
+        invoice.calculate_taxes();
+        let total = invoice.calculate_total();
+        assert!((total - 131.4).abs() < 0.001);
+        assert!((invoice.total_products() - 110.0).abs() < 0.001);
+        assert!((invoice.total_taxes() - 21.4).abs() < 0.001);
+        assert_eq!(invoice.taxes.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_taxes_calculation_with_sales_tax() {
+        let raw_invoice = "
+        2024-12-27	Wine	Alcohol	Trader Joes	 $20.00
+        2024-12-27	Bread	Comida	Trader Joes	 $5.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_engine(from_code("us-ny").unwrap());
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_products(), 25.0);
+        assert_eq!(invoice.total_taxes(), 1.0);
+        assert_eq!(invoice.calculate_total(), 26.0);
+    }
+
+    #[test]
+    fn test_taxes_calculation_with_ieps() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Vino Tinto	Alcohol	walmart	 $148.00
+        viernes, 27 de diciembre de 2024	Jabón	Abarrotes	walmart	 $67.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_engine(from_code("mx-ieps").unwrap());
+        invoice.calculate_taxes();
+        let taxes = invoice.taxes.as_ref().unwrap();
+        assert_eq!(taxes.len(), 2);
+        assert!(taxes.iter().any(|x| x.product == "IEPS"));
+        assert!((invoice.calculate_total() - 215.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_exclusive_pricing_mode() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Torta	Restaurante	name	 $100.00
+        viernes, 27 de diciembre de 2024	Agua	Restaurante	name	 $50.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_pricing_mode(PricingMode::Exclusive);
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_products(), 150.0);
+        assert!((invoice.total_taxes() - 24.0).abs() < 0.001);
+        assert!((invoice.calculate_total() - 174.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_inclusive_pricing_mode_with_exclusive_engine() {
+        let raw_invoice = "
+        2024-12-27	Wine	Alcohol	Trader Joes	 $20.80
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products)
+            .with_engine(from_code("us-ny").unwrap())
+            .with_pricing_mode(PricingMode::Inclusive);
+        invoice.calculate_taxes();
+        assert!((invoice.total_products() - 20.0).abs() < 0.001);
+        assert!((invoice.total_taxes() - 0.8).abs() < 0.001);
+        assert!((invoice.calculate_total() - 20.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_rounding_per_line() {
+        let mut products = [
+            single_product(0.05),
+            single_product(0.05),
+            single_product(0.05),
+        ];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products)
+            .with_pricing_mode(PricingMode::Exclusive)
+            .with_rounding(RoundingPolicy::new(
+                RoundingScope::Line,
+                RoundingMode::HalfUp,
+                2,
+            ));
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_taxes(), 0.03);
+        assert_eq!(invoice.rounding_adjustment(), -0.01);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 0.17);
+    }
+
+    #[test]
+    fn test_rounding_per_total() {
+        let mut products = [
+            single_product(0.05),
+            single_product(0.05),
+            single_product(0.05),
+        ];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products)
+            .with_pricing_mode(PricingMode::Exclusive)
+            .with_rounding(RoundingPolicy::new(
+                RoundingScope::Total,
+                RoundingMode::HalfUp,
+                2,
+            ));
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_taxes(), 0.02);
+        assert_eq!(invoice.rounding_adjustment(), 0.0);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 0.17);
+    }
+
+    #[test]
+    fn test_rounding_per_line_inclusive() {
+        let mut products = [
+            single_product(10.0),
+            single_product(10.0),
+            single_product(10.0),
+        ];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_rounding(RoundingPolicy::new(
+            RoundingScope::Line,
+            RoundingMode::HalfEven,
+            2,
+        ));
+        invoice.calculate_taxes();
+        assert_eq!(invoice.total_products(), 25.86);
+        assert_eq!(invoice.total_taxes(), 4.14);
+        assert_eq!(round_to_two_decimals(invoice.calculate_total()), 30.0);
+    }
+
+    #[test]
+    fn test_adjustments_history() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Torta	Restaurante	name	 $400.00
+        viernes, 27 de diciembre de 2024	Vino Tinto	Restaurante	name	 $253.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let invoice_products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(invoice_products);
+        invoice.tips_from_products(0.10);
+        invoice.calculate_taxes();
+        let steps = invoice.products[0]
+            .adjustments
+            .iter()
+            .map(|x| x.step)
+            .collect::<Vec<_>>();
+        assert_eq!(steps[..2], ["remove tips", "estimate taxes"]);
+        let first = &invoice.products[0].adjustments[0];
+        assert_eq!(first.before, 400.0);
+        let last = invoice.products[0].adjustments.last().unwrap();
+        assert_eq!(Some(last.after), invoice.products[0].price);
+    }
+
+    #[test]
+    fn test_allocations() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Torta	Restaurante	name	 $400.00
+        viernes, 27 de diciembre de 2024	Vino Tinto	Restaurante	name	 $253.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products);
+        invoice.tips_from_products(0.10);
+        invoice.calculate_taxes();
+        let allocations = invoice.allocations();
+        assert_eq!(allocations[0].original, 400.0);
+        assert!(allocations[0].delta().abs() < 0.001);
+        assert!(allocations[1].delta().abs() < 0.001);
+        let tips: f64 = allocations.iter().map(|x| x.tip).sum();
+        assert!((tips - invoice.total_tips()).abs() < 0.001);
+        let taxes: f64 = allocations.iter().map(|x| x.tax).sum();
+        assert!((taxes - invoice.total_taxes()).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_allocations_exclusive() {
+        let mut products = [single_product(100.0), single_product(50.0)];
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_pricing_mode(PricingMode::Exclusive);
+        invoice.calculate_taxes();
+        let allocations = invoice.allocations();
+        assert!((allocations[0].tax - 16.0).abs() < 0.001);
+        assert!((allocations[1].delta() - 8.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_category_breakdown() {
+        let raw_invoice = "
+        viernes, 27 de diciembre de 2024	Vino Rosado	Alcohol	walmart	 $256.00
+        viernes, 27 de diciembre de 2024	Jabón	Abarrotes	walmart	 $67.00
+        viernes, 27 de diciembre de 2024	Vino Tinto	Alcohol	walmart	 $148.00
+        viernes, 27 de diciembre de 2024	Rummy	Ocio	walmart	 $185.00
+        ";
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products);
+        invoice.calculate_taxes();
+
+        let categories = invoice.category_breakdown(false);
+        let names = categories
+            .iter()
+            .map(|x| x.category.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Alcohol", "Abarrotes", "Ocio"]);
+        assert!((categories[0].subtotal - 404.0 / (1.0 + VAT)).abs() < 0.001);
+        assert!((categories[0].total() - 404.0).abs() < 0.001);
+        let shares: f64 = categories.iter().map(|x| x.share).sum();
+        assert!((shares - 1.0).abs() < 0.001);
+
+        let categories = invoice.category_breakdown(true);
+        let names = categories
+            .iter()
+            .map(|x| x.category.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Alcohol", "Ocio", "Abarrotes"]);
+    }
 }
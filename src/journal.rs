@@ -0,0 +1,424 @@
+use crate::date::Date;
+use crate::invoice::Invoice;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum JournalFormat {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+/// Accounts the postings go to, read from a TOML file such as:
+///
+/// ```toml
+/// payment = "Liabilities:CreditCard"
+///
+/// [categories]
+/// Alcohol = "Expenses:Food:Alcohol"
+///
+/// [taxes]
+/// IVA = "Expenses:Taxes:VAT"
+/// ```
+///
+/// Categories without an account go to `Expenses:<category>` and taxes to
+/// `Expenses:Taxes:<tax>`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Accounts {
+    #[serde(default = "default_expenses")]
+    pub expenses: String,
+    #[serde(default = "default_payment")]
+    pub payment: String,
+    #[serde(default = "default_tips")]
+    pub tips: String,
+    #[serde(default = "default_rounding")]
+    pub rounding: String,
+    #[serde(default)]
+    pub categories: BTreeMap<String, String>,
+    #[serde(default)]
+    pub taxes: BTreeMap<String, String>,
+}
+
+fn default_expenses() -> String {
+    "Expenses".to_owned()
+}
+
+fn default_payment() -> String {
+    "Assets:Cash".to_owned()
+}
+
+fn default_tips() -> String {
+    "Expenses:Tips".to_owned()
+}
+
+fn default_rounding() -> String {
+    "Expenses:Rounding".to_owned()
+}
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Accounts {
+            expenses: default_expenses(),
+            payment: default_payment(),
+            tips: default_tips(),
+            rounding: default_rounding(),
+            categories: BTreeMap::new(),
+            taxes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Accounts {
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    fn find(names: &BTreeMap<String, String>, name: &str) -> Option<String> {
+        names
+            .iter()
+            .find(|(x, _)| x.trim().eq_ignore_ascii_case(name.trim()))
+            .map(|(_, account)| account.clone())
+    }
+
+    pub fn category(&self, category: &str) -> String {
+        Self::find(&self.categories, category).unwrap_or_else(|| match category.trim() {
+            "" => format!("{}:Other", self.expenses),
+            x => format!("{}:{}", self.expenses, x),
+        })
+    }
+
+    pub fn tax(&self, tax: &str) -> String {
+        Self::find(&self.taxes, tax)
+            .unwrap_or_else(|| format!("{}:Taxes:{}", self.expenses, tax.trim()))
+    }
+}
+
+/// An amount going to an account, in cents so the transaction balances exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: String,
+    pub cents: i64,
+}
+
+/// An invoice as a double-entry transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub date: String,
+    pub payee: String,
+    pub note: String,
+    pub currency: String,
+    pub postings: Vec<Posting>,
+}
+
+fn cents(value: f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+impl Transaction {
+    /// One posting per category with its pre-tax amount, one per tax line, the tips, the
+    /// invoice rounding and the payment of the invoice total, all in the reporting currency.
+    ///
+    /// Rounding every posting to cents may leave the transaction off by up to a cent per
+    /// posting, which goes to the rounding account. Any larger difference means the
+    /// allocations do not add up to the invoice total and is an error, as is a date the
+    /// journals cannot read.
+    pub fn from_invoice(
+        note: &str,
+        invoice: &Invoice,
+        accounts: &Accounts,
+    ) -> Result<Self, String> {
+        let first = invoice.products().next();
+        let raw_date = first.map_or("", |x| x.date.trim());
+        let date =
+            Date::parse(raw_date).ok_or_else(|| format!("unrecognized date {:?}", raw_date))?;
+
+        let rate = invoice.exchange_rate();
+        let mut postings = Vec::new();
+        let mut add = |account: String, amount: f64| {
+            let cents = cents(amount * rate);
+            if cents == 0 {
+                return;
+            }
+            match postings
+                .iter_mut()
+                .find(|x: &&mut Posting| x.account == account)
+            {
+                Some(posting) => posting.cents += cents,
+                None => postings.push(Posting { account, cents }),
+            }
+        };
+        for x in invoice.category_breakdown(false) {
+            add(accounts.category(&x.category), x.subtotal);
+        }
+        for x in invoice.tax_lines() {
+            add(accounts.tax(&x.product), x.price.unwrap_or(0.0));
+        }
+        add(accounts.tips.clone(), invoice.total_tips());
+        // Everything the rounding policy adds or removes: the adjustment and, when rounding
+        // the total, the rounding of the products.
+        let rounding = invoice.calculate_total()
+            - invoice.total_products()
+            - invoice.total_tips()
+            - invoice.total_taxes();
+        add(accounts.rounding.clone(), rounding);
+
+        let total = cents(invoice.calculate_total() * rate);
+        balance(&mut postings, total, &accounts.rounding)?;
+        postings.push(Posting {
+            account: accounts.payment.clone(),
+            cents: -total,
+        });
+
+        Ok(Transaction {
+            date: date.to_string(),
+            payee: first.map_or(String::new(), |x| x.place.trim().to_owned()),
+            note: note.to_owned(),
+            currency: invoice.report_currency().to_owned(),
+            postings,
+        })
+    }
+}
+
+/// Sends to the rounding account the cents the postings are off from the total, up to one
+/// per posting.
+fn balance(postings: &mut Vec<Posting>, total: i64, rounding: &str) -> Result<(), String> {
+    let residual = total - postings.iter().map(|x| x.cents).sum::<i64>();
+    if residual.abs() > postings.len().max(1) as i64 {
+        return Err(format!(
+            "postings add up to {} but the total is {}",
+            format_cents(total - residual),
+            format_cents(total)
+        ));
+    }
+    if residual != 0 {
+        match postings.iter_mut().find(|x| x.account == rounding) {
+            Some(posting) => posting.cents += residual,
+            None => postings.push(Posting {
+                account: rounding.to_owned(),
+                cents: residual,
+            }),
+        }
+    }
+    Ok(())
+}
+
+/// Beancount accounts are capitalized components of letters, digits and dashes.
+fn beancount_account(account: &str) -> String {
+    account
+        .split(':')
+        .map(|component| {
+            let component = component
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join("-")
+                .chars()
+                .filter(|x| x.is_alphanumeric() || *x == '-')
+                .collect::<String>();
+            let mut chars = component.chars();
+            match chars.next() {
+                Some(x) if x.is_alphanumeric() => x.to_uppercase().chain(chars).collect(),
+                Some(_) => format!("X{}", component),
+                None => "Other".to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Single spaces, since two of them end the account name in Ledger journals.
+fn ledger_account(account: &str) -> String {
+    account.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Renders the transactions as a journal. Beancount journals start with the `open`
+/// directives of every account used.
+pub fn to_journal(transactions: &[Transaction], format: JournalFormat) -> String {
+    let account = |x: &str| match format {
+        JournalFormat::Beancount => beancount_account(x),
+        _ => ledger_account(x),
+    };
+    let mut blocks = Vec::new();
+    if format == JournalFormat::Beancount {
+        let opened = transactions
+            .iter()
+            .flat_map(|x| x.postings.iter().map(|x| account(&x.account)))
+            .collect::<BTreeSet<_>>();
+        if let Some(date) = transactions.iter().map(|x| x.date.as_str()).min() {
+            let opens = opened
+                .iter()
+                .map(|x| format!("{} open {}", date, x))
+                .collect::<Vec<_>>();
+            blocks.push(opens.join("\n"));
+        }
+    }
+    for x in transactions {
+        let mut lines = vec![match format {
+            JournalFormat::Ledger => format!("{} {}", x.date, x.payee),
+            JournalFormat::Hledger => format!("{} {} | {}", x.date, x.payee, x.note),
+            JournalFormat::Beancount => {
+                format!("{} * {} {}", x.date, quote(&x.payee), quote(&x.note))
+            }
+        }];
+        if format == JournalFormat::Ledger {
+            lines.push(format!("    ; {}", x.note));
+        }
+        let accounts = x
+            .postings
+            .iter()
+            .map(|x| account(&x.account))
+            .collect::<Vec<_>>();
+        let width = accounts
+            .iter()
+            .map(|x| x.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (name, posting) in accounts.iter().zip(&x.postings) {
+            lines.push(format!(
+                "    {:<width$}  {:>12} {}",
+                name,
+                format_cents(posting.cents),
+                x.currency
+            ));
+        }
+        blocks.push(lines.join("\n"));
+    }
+    blocks.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+    use crate::tax_engine::from_code;
+
+    const ACCOUNTS: &str = r#"
+payment = "Liabilities:Card"
+
+[categories]
+alcohol = "Expenses:Food:Alcohol"
+
+[taxes]
+IVA = "Expenses:Taxes:VAT"
+"#;
+
+    fn transaction(file: &str, accounts: &Accounts) -> Transaction {
+        let mut products = read_file(file);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_engine(from_code("mx-ieps").unwrap());
+        invoice.tips_from_products(0.1);
+        invoice.calculate_taxes();
+        Transaction::from_invoice("t.tsv", &invoice, accounts).unwrap()
+    }
+
+    #[test]
+    fn test_from_invoice() {
+        let accounts = Accounts::from_toml(ACCOUNTS).unwrap();
+        let x = transaction(
+            "2024-12-27\tVino Tinto\tAlcohol\twalmart\t$148.00\n\
+             2024-12-27\tPasta\tComida\twalmart\t$22.50\n\
+             2024-12-27\tSidra\tAlcohol\twalmart\t$88.45\n",
+            &accounts,
+        );
+        let names = x
+            .postings
+            .iter()
+            .map(|x| x.account.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names[..5],
+            [
+                "Expenses:Food:Alcohol",
+                "Expenses:Comida",
+                "Expenses:Taxes:IEPS",
+                "Expenses:Taxes:VAT",
+                "Expenses:Tips"
+            ]
+        );
+        assert_eq!(names.last(), Some(&"Liabilities:Card"));
+        assert_eq!(x.postings.iter().map(|x| x.cents).sum::<i64>(), 0);
+        assert_eq!(x.postings.last().unwrap().cents, -25895);
+        assert_eq!(x.date, "2024-12-27");
+        assert_eq!(x.payee, "walmart");
+    }
+
+    #[test]
+    fn test_to_journal() {
+        let x = transaction(
+            "27/12/2024\tRefresco \"light\"\tComida, bebida\tOxxo\t$20.00\n",
+            &Accounts::default(),
+        );
+        let ledger = to_journal(std::slice::from_ref(&x), JournalFormat::Ledger);
+        let lines = ledger.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "2024-12-27 Oxxo");
+        assert_eq!(lines[1], "    ; t.tsv");
+        assert!(lines[2].starts_with("    Expenses:Comida, bebida  "));
+        assert!(lines.last().unwrap().ends_with("-20.00 MXN"));
+
+        let hledger = to_journal(std::slice::from_ref(&x), JournalFormat::Hledger);
+        assert!(hledger.starts_with("2024-12-27 Oxxo | t.tsv\n"));
+
+        let beancount = to_journal(&[x], JournalFormat::Beancount);
+        let lines = beancount.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"2024-12-27 open Expenses:Comida-bebida"));
+        assert!(lines.contains(&"2024-12-27 * \"Oxxo\" \"t.tsv\""));
+    }
+
+    #[test]
+    fn test_converted_invoice() {
+        let mut products = read_file("2025-01-04\tBeer\tAlcohol\tPub\t US$11.60");
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_exchange("MXN", 20.0);
+        invoice.calculate_taxes();
+        let x = Transaction::from_invoice("usd.tsv", &invoice, &Accounts::default()).unwrap();
+        assert_eq!(x.currency, "MXN");
+        assert_eq!(x.postings[0].cents, 20000);
+        assert_eq!(x.postings.last().unwrap().cents, -23200);
+    }
+
+    #[test]
+    fn test_unrecognized_date() {
+        let mut products = read_file("viernes, 27 de diciembre\tVino\tAlcohol\tBar\t$100.00");
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products);
+        invoice.calculate_taxes();
+        let error = Transaction::from_invoice("t.tsv", &invoice, &Accounts::default());
+        assert_eq!(
+            error,
+            Err("unrecognized date \"viernes, 27 de diciembre\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_balance() {
+        let posting = |account: &str, cents| Posting {
+            account: account.to_owned(),
+            cents,
+        };
+        let mut postings = vec![posting("A", 3333), posting("B", 3333), posting("C", 3333)];
+        balance(&mut postings, 10000, "R").unwrap();
+        assert_eq!(postings.last(), Some(&posting("R", 1)));
+
+        let mut postings = vec![posting("A", 9000), posting("B", 900)];
+        assert_eq!(
+            balance(&mut postings, 10000, "R"),
+            Err("postings add up to 99.00 but the total is 100.00".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_format_cents() {
+        assert_eq!(format_cents(-5), "-0.05");
+        assert_eq!(format_cents(123456), "1234.56");
+    }
+}
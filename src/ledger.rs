@@ -0,0 +1,545 @@
+use crate::currency::DEFAULT_CURRENCY;
+use crate::date::Date;
+use crate::duplicates::Receipt;
+use crate::history::Purchase;
+use crate::invoice::Invoice;
+use crate::report::{Entry, TaxEntry};
+use clap::ValueEnum;
+use rusqlite::{params, Connection, OpenFlags, Result};
+use serde::Serialize;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS invoices (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL,
+    date TEXT NOT NULL,
+    iso_date TEXT,
+    place TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    pricing TEXT NOT NULL,
+    products REAL NOT NULL,
+    tips REAL NOT NULL,
+    taxes REAL NOT NULL,
+    rounding REAL NOT NULL,
+    total REAL NOT NULL,
+    fingerprint TEXT,
+    currency TEXT,
+    exchange_rate REAL,
+    report_currency TEXT
+);
+CREATE TABLE IF NOT EXISTS products (
+    id INTEGER PRIMARY KEY,
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id),
+    date TEXT NOT NULL,
+    iso_date TEXT,
+    product TEXT NOT NULL,
+    product_type TEXT NOT NULL,
+    place TEXT NOT NULL,
+    original_price REAL NOT NULL,
+    price REAL NOT NULL,
+    tax REAL NOT NULL,
+    tip REAL NOT NULL,
+    quantity REAL
+);
+CREATE TABLE IF NOT EXISTS invoice_taxes (
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id),
+    name TEXT NOT NULL,
+    amount REAL NOT NULL
+);
+";
+
+/// Bumped when the way receipts are fingerprinted changes.
+const FINGERPRINT_VERSION: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Grouping {
+    Month,
+    Category,
+    Place,
+}
+
+impl Grouping {
+    fn column(&self) -> &'static str {
+        match self {
+            Grouping::Month => "COALESCE(substr(iso_date, 1, 7), 'unknown')",
+            Grouping::Category => "product_type",
+            Grouping::Place => "place",
+        }
+    }
+}
+
+/// Stored spending for one month, category or place.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LedgerTotal {
+    pub key: String,
+    pub invoices: i64,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub tip: f64,
+    pub total: f64,
+}
+
+/// Local SQLite store of computed invoices.
+pub struct Ledger {
+    connection: Connection,
+}
+
+impl Ledger {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ledger::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens a ledger that must already exist, without creating an empty one.
+    pub fn open_existing(path: &Path) -> Result<Self> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Ledger::with_connection(Connection::open_with_flags(path, flags)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Ledger::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        let ledger = Ledger { connection };
+        ledger.add_column_if_missing("invoices", "fingerprint", "TEXT")?;
+        ledger.add_column_if_missing("products", "quantity", "REAL")?;
+        ledger.add_column_if_missing("invoices", "currency", "TEXT")?;
+        ledger.add_column_if_missing("invoices", "exchange_rate", "REAL")?;
+        ledger.add_column_if_missing("invoices", "report_currency", "TEXT")?;
+        ledger.refresh_fingerprints()?;
+        ledger.fill_missing_fingerprints()?;
+        ledger.connection.execute_batch(
+            "CREATE INDEX IF NOT EXISTS invoices_fingerprint ON invoices (fingerprint)",
+        )?;
+        Ok(ledger)
+    }
+
+    /// Recomputes, once, the fingerprints saved before names were compared without accents
+    /// and amounts converted into the reporting currency.
+    fn refresh_fingerprints(&self) -> Result<()> {
+        let version = self
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))?;
+        if version < FINGERPRINT_VERSION {
+            self.connection.execute_batch(&format!(
+                "UPDATE invoices SET fingerprint = NULL; PRAGMA user_version = {}",
+                FINGERPRINT_VERSION
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Fingerprints the invoices saved before the column existed.
+    fn fill_missing_fingerprints(&self) -> Result<()> {
+        let missing = self
+            .connection
+            .prepare("SELECT id FROM invoices WHERE fingerprint IS NULL")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        if missing.is_empty() {
+            return Ok(());
+        }
+        for (id, receipt) in self.stored_receipts()? {
+            if missing.contains(&id) {
+                self.connection.execute(
+                    "UPDATE invoices SET fingerprint = ?1 WHERE id = ?2",
+                    params![receipt.fingerprint(), id],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Upgrades ledgers created before a column existed.
+    fn add_column_if_missing(&self, table: &str, column: &str, kind: &str) -> Result<()> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+        let columns = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        if !columns.iter().any(|x| x == column) {
+            self.connection.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, kind
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Saves a computed invoice with its products, returning the invoice id. Amounts are kept
+    /// in the invoice currency along with the rate into the reporting currency, which the
+    /// totals and report entries apply.
+    pub fn save(&mut self, source: &str, invoice: &Invoice) -> Result<i64> {
+        let resume = invoice.resume(false);
+        let first = invoice.products().next();
+        let date = first.map_or("", |x| x.date.trim());
+        let place = first.map_or("", |x| x.place.trim());
+        let iso_date = Date::parse(date).map(|x| x.to_string());
+        let fingerprint = Receipt::from_invoice(source, invoice).fingerprint();
+        let exchange_rate = invoice.conversion().map(|x| x.rate);
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO invoices
+                (source, date, iso_date, place, engine, pricing, products, tips, taxes, rounding, total,
+                 fingerprint, currency, exchange_rate, report_currency)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                source,
+                date,
+                iso_date,
+                place,
+                resume.engine,
+                resume.pricing,
+                resume.products,
+                resume.tips,
+                resume.taxes,
+                resume.rounding,
+                resume.total,
+                fingerprint,
+                invoice.currency(),
+                exchange_rate,
+                invoice.report_currency()
+            ],
+        )?;
+        let invoice_id = transaction.last_insert_rowid();
+        for (product, allocation) in invoice.products().zip(invoice.allocations()) {
+            transaction.execute(
+                "INSERT INTO products
+                    (invoice_id, date, iso_date, product, product_type, place, original_price, price, tax, tip,
+                     quantity)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    invoice_id,
+                    product.date.trim(),
+                    Date::parse(&product.date).map(|x| x.to_string()),
+                    product.product.trim(),
+                    product.product_type.trim(),
+                    product.place.trim(),
+                    allocation.original,
+                    allocation.base,
+                    allocation.tax,
+                    allocation.tip,
+                    product.quantity
+                ],
+            )?;
+        }
+        for tax in invoice.tax_lines() {
+            transaction.execute(
+                "INSERT INTO invoice_taxes (invoice_id, name, amount) VALUES (?1, ?2, ?3)",
+                params![invoice_id, tax.product, tax.price.unwrap_or(0.0)],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(invoice_id)
+    }
+
+    /// Stored invoices as receipts in the reporting currency, with the source naming the
+    /// invoice id.
+    pub fn receipts(&self) -> Result<Vec<Receipt>> {
+        Ok(self
+            .stored_receipts()?
+            .into_iter()
+            .map(|(_, x)| x)
+            .collect())
+    }
+
+    fn stored_receipts(&self) -> Result<Vec<(i64, Receipt)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, source, date, place, total * COALESCE(exchange_rate, 1)
+                 FROM invoices ORDER BY id",
+        )?;
+        let invoices = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let mut lines = self.connection.prepare(
+            "SELECT product, original_price * COALESCE(exchange_rate, 1)
+             FROM products JOIN invoices ON invoices.id = products.invoice_id
+             WHERE invoice_id = ?1 ORDER BY products.id",
+        )?;
+        invoices
+            .into_iter()
+            .map(|(id, source, date, place, total)| {
+                let products = lines
+                    .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<Vec<_>>>()?;
+                let source = format!("invoice {} ({})", id, source);
+                Ok((id, Receipt::new(&source, &date, &place, products, total)))
+            })
+            .collect()
+    }
+
+    /// The stored invoice with the same fingerprint, named like the receipts' sources.
+    pub fn find_fingerprint(&self, fingerprint: &str) -> Result<Option<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, source FROM invoices WHERE fingerprint = ?1 ORDER BY id LIMIT 1",
+        )?;
+        let mut rows = statement.query_map([fingerprint], |row| {
+            Ok(format!(
+                "invoice {} ({})",
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?
+            ))
+        })?;
+        rows.next().transpose()
+    }
+
+    /// Name and category of every stored product.
+    pub fn categorized_products(&self) -> Result<Vec<(String, String)>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT product, product_type FROM products ORDER BY id")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Every purchase of a product, matched by name ignoring case, oldest first, in the
+    /// reporting currency.
+    pub fn purchases(&self, product: &str) -> Result<Vec<Purchase>> {
+        let mut statement = self.connection.prepare(
+            "SELECT COALESCE(products.iso_date, products.date), products.place,
+                    original_price * COALESCE(exchange_rate, 1), price * COALESCE(exchange_rate, 1),
+                    tax * COALESCE(exchange_rate, 1), quantity
+             FROM products JOIN invoices ON invoices.id = products.invoice_id
+             WHERE lower(trim(product)) = lower(trim(?1))
+             ORDER BY products.iso_date IS NULL, products.iso_date, products.id",
+        )?;
+        let rows = statement.query_map([product], |row| {
+            Ok(Purchase {
+                date: row.get(0)?,
+                place: row.get(1)?,
+                original_price: row.get(2)?,
+                price: row.get(3)?,
+                tax: row.get(4)?,
+                quantity: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Currencies the stored amounts are reported in, which can only be added up when there
+    /// is a single one.
+    pub fn currencies(&self) -> Result<Vec<String>> {
+        self.connection
+            .prepare(
+                "SELECT DISTINCT COALESCE(report_currency, currency, ?1) FROM invoices ORDER BY 1",
+            )?
+            .query_map([DEFAULT_CURRENCY], |row| row.get(0))?
+            .collect()
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let mut statement = self.connection.prepare(
+            "SELECT products.iso_date, product_type, products.place,
+                    price * COALESCE(exchange_rate, 1), tax * COALESCE(exchange_rate, 1),
+                    tip * COALESCE(exchange_rate, 1)
+             FROM products JOIN invoices ON invoices.id = products.invoice_id
+             ORDER BY products.id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(Entry {
+                date: row
+                    .get::<_, Option<String>>(0)?
+                    .and_then(|x| Date::parse(&x)),
+                category: row.get(1)?,
+                place: row.get(2)?,
+                subtotal: row.get(3)?,
+                tax: row.get(4)?,
+                tip: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn tax_entries(&self) -> Result<Vec<TaxEntry>> {
+        let mut statement = self.connection.prepare(
+            "SELECT invoices.iso_date, invoice_taxes.name,
+                    invoice_taxes.amount * COALESCE(invoices.exchange_rate, 1)
+             FROM invoice_taxes JOIN invoices ON invoices.id = invoice_taxes.invoice_id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(TaxEntry {
+                date: row
+                    .get::<_, Option<String>>(0)?
+                    .and_then(|x| Date::parse(&x)),
+                name: row.get(1)?,
+                amount: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn totals_by(&self, grouping: Grouping) -> Result<Vec<LedgerTotal>> {
+        let query = format!(
+            "SELECT {column} AS key, COUNT(DISTINCT invoice_id), SUM(price * rate),
+                    SUM(tax * rate), SUM(tip * rate), SUM((price + tax + tip) * rate)
+             FROM (SELECT products.*, COALESCE(invoices.exchange_rate, 1) AS rate
+                   FROM products JOIN invoices ON invoices.id = products.invoice_id)
+             GROUP BY key ORDER BY key",
+            column = grouping.column()
+        );
+        let mut statement = self.connection.prepare(&query)?;
+        let rows = statement.query_map([], |row| {
+            Ok(LedgerTotal {
+                key: row.get(0)?,
+                invoices: row.get(1)?,
+                subtotal: row.get(2)?,
+                tax: row.get(3)?,
+                tip: row.get(4)?,
+                total: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+
+    fn save(ledger: &mut Ledger, raw_invoice: &str) {
+        let mut products = read_file(raw_invoice);
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products);
+        invoice.calculate_taxes();
+        ledger.save("test.tsv", &invoice).unwrap();
+    }
+
+    #[test]
+    fn test_totals_by_category_month_and_place() {
+        let mut ledger = Ledger::open_in_memory().unwrap();
+        save(
+            &mut ledger,
+            "
+            viernes, 27 de diciembre de 2024	Vino Tinto	Alcohol	walmart	 $116.00
+            viernes, 27 de diciembre de 2024	Jabón	Abarrotes	walmart	 $58.00
+            ",
+        );
+        save(
+            &mut ledger,
+            "
+            2025-01-03	Cerveza	Alcohol	Bar	 $232.00
+            ",
+        );
+
+        let categories = ledger.totals_by(Grouping::Category).unwrap();
+        assert_eq!(categories.len(), 2);
+        assert_eq!(categories[1].key, "Alcohol");
+        assert_eq!(categories[1].invoices, 2);
+        assert!((categories[1].subtotal - 300.0).abs() < 0.001);
+        assert!((categories[1].total - 348.0).abs() < 0.001);
+
+        let months = ledger.totals_by(Grouping::Month).unwrap();
+        let keys = months.iter().map(|x| x.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["2024-12", "2025-01"]);
+        assert!((months[0].tax - 24.0).abs() < 0.001);
+
+        let places = ledger.totals_by(Grouping::Place).unwrap();
+        assert_eq!(places[0].key, "Bar");
+
+        let receipts = ledger.receipts().unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].source, "invoice 1 (test.tsv)");
+        assert_eq!(receipts[0].lines.len(), 2);
+
+        let products = ledger.categorized_products().unwrap();
+        assert_eq!(products[0], ("Vino Tinto".to_owned(), "Alcohol".to_owned()));
+
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].date, Date::new(2025, 1, 3));
+        let taxes = ledger.tax_entries().unwrap();
+        assert_eq!(taxes.len(), 2);
+        assert_eq!(taxes[0].name, "IVA");
+    }
+
+    #[test]
+    fn test_converted_totals() {
+        let mut ledger = Ledger::open_in_memory().unwrap();
+        save(&mut ledger, "2025-01-03\tCerveza\tAlcohol\tBar\t $116.00");
+        let mut products = read_file("2025-01-04\tBeer\tAlcohol\tPub\t US$11.60");
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_exchange("MXN", 20.0);
+        invoice.calculate_taxes();
+        ledger.save("usd.tsv", &invoice).unwrap();
+        assert_eq!(ledger.currencies().unwrap(), ["MXN"]);
+
+        let totals = ledger.totals_by(Grouping::Category).unwrap();
+        assert!((totals[0].subtotal - 300.0).abs() < 0.001);
+        assert!((totals[0].total - 348.0).abs() < 0.001);
+        let entries = ledger.entries().unwrap();
+        assert!((entries[1].tax - 32.0).abs() < 0.001);
+        let taxes = ledger.tax_entries().unwrap();
+        assert!((taxes[1].amount - 32.0).abs() < 0.001);
+        let purchases = ledger.purchases("beer").unwrap();
+        assert!((purchases[0].original_price - 232.0).abs() < 0.001);
+        assert!((purchases[0].tax - 32.0).abs() < 0.001);
+        let receipts = ledger.receipts().unwrap();
+        assert!((receipts[1].total - 232.0).abs() < 0.001);
+        assert!((receipts[1].lines[0].1 - 232.0).abs() < 0.001);
+
+        save(&mut ledger, "2025-01-05\tBeer\tAlcohol\tPub\t US$11.60");
+        assert_eq!(ledger.currencies().unwrap(), ["MXN", "USD"]);
+    }
+
+    #[test]
+    fn test_purchases() {
+        let mut ledger = Ledger::open_in_memory().unwrap();
+        save(&mut ledger, "2025-02-01\tCerveza\tAlcohol\tBar\t $69.60\t3");
+        save(&mut ledger, "2025-01-03\tcerveza \tAlcohol\tOxxo\t $23.20");
+        save(&mut ledger, "2025-01-03\tVino\tAlcohol\tOxxo\t $116.00");
+
+        let purchases = ledger.purchases("CERVEZA").unwrap();
+        assert_eq!(purchases.len(), 2);
+        assert_eq!(purchases[0].date, "2025-01-03");
+        assert_eq!(purchases[0].quantity, None);
+        assert_eq!(purchases[1].place, "Bar");
+        assert_eq!(purchases[1].quantity, Some(3.0));
+        assert!((purchases[1].price - 60.0).abs() < 0.001);
+        assert!((purchases[1].unit_cost() - 23.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_find_fingerprint() {
+        let mut ledger = Ledger::open_in_memory().unwrap();
+        save(&mut ledger, "2025-01-03\tVino\tAlcohol\tOxxo\t $116.00");
+        let receipt = ledger.receipts().unwrap().remove(0);
+        assert_eq!(
+            ledger.find_fingerprint(&receipt.fingerprint()).unwrap(),
+            Some("invoice 1 (test.tsv)".to_owned())
+        );
+        assert_eq!(ledger.find_fingerprint("0").unwrap(), None);
+
+        ledger
+            .connection
+            .execute_batch("UPDATE invoices SET fingerprint = NULL")
+            .unwrap();
+        ledger.fill_missing_fingerprints().unwrap();
+        assert!(ledger
+            .find_fingerprint(&receipt.fingerprint())
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_open_existing_does_not_create() {
+        let path = std::env::temp_dir().join(format!("missing-ledger-{}.db", std::process::id()));
+        assert!(Ledger::open_existing(&path).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -1,22 +1,322 @@
+mod amount;
+mod bank;
+mod basket;
+mod budget;
+mod catalog;
+mod categorizer;
+mod currency;
+mod date;
+mod duplicates;
+mod history;
+mod input;
 mod invoice;
+mod journal;
+mod ledger;
+mod output;
 mod product;
 mod reader;
+mod reconcile;
+mod report;
+mod rounding;
+mod spreadsheet;
+mod store;
+mod tax_engine;
+#[cfg(test)]
+mod test_support;
+mod ticket;
 
+use crate::amount::NumberFormat;
+use crate::bank::StatementFormat;
+use crate::basket::Basket;
+use crate::budget::Budgets;
+use crate::catalog::Catalog;
+use crate::categorizer::Rules;
+use crate::currency::ExchangeRates;
+use crate::date::Date;
+use crate::duplicates::{DuplicateKind, Receipt};
 use crate::invoice::Invoice;
+use crate::journal::{Accounts, JournalFormat};
+use crate::ledger::{Grouping, Ledger};
+use crate::output::{InvoiceFormat, OutputFormat};
 use crate::product::Product;
-use clap::Parser;
+use crate::report::Period;
+use crate::rounding::{RoundingMode, RoundingPolicy, RoundingScope};
+use crate::store::StoreRegistry;
+use crate::tax_engine::PricingMode;
+use crate::ticket::{Ticket, Verification};
+use clap::{Parser, Subcommand};
+use encoding_rs::Encoding;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(name = "file", help = "File to read")]
-    file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(name = "file", help = "File to read, - for stdin", required = true)]
+    file: Option<PathBuf>,
     #[arg(short, long, help = "Show all products", default_value = "false")]
     show_all: bool,
+    #[command(flatten)]
+    calculation: CalculationArgs,
+    #[arg(
+        short,
+        long,
+        help = "Explain how every price was adjusted",
+        default_value = "false"
+    )]
+    explain: bool,
+    #[arg(
+        short,
+        long,
+        help = "Compare original and computed prices side by side",
+        default_value = "false"
+    )]
+    compare: bool,
+    #[arg(short, long, help = "Output format", default_value = "text")]
+    format: InvoiceFormat,
+    #[arg(
+        long,
+        help = "Sort the category breakdown by amount",
+        default_value = "false"
+    )]
+    sort_categories: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compute invoices and save them in the ledger
+    Import {
+        #[arg(help = "Files to import, - for stdin", required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(
+            long,
+            help = "Do not import receipts that look like duplicates",
+            default_value = "false"
+        )]
+        skip_duplicates: bool,
+        #[command(flatten)]
+        calculation: CalculationArgs,
+    },
+    /// Write receipts as plain-text accounting transactions
+    Export {
+        #[arg(help = "Files to export, - for stdin", required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Journal format", default_value = "ledger")]
+        to: JournalFormat,
+        #[arg(long, help = "Accounts of the categories, taxes, tips and payment")]
+        accounts: Option<PathBuf>,
+        #[command(flatten)]
+        calculation: CalculationArgs,
+    },
+    /// Show stored spending totals
+    Query {
+        #[arg(help = "Group totals by month, category or place")]
+        by: Grouping,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(short, long, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Report spending per month or year from files or the ledger
+    Report {
+        #[arg(help = "Files to report on, the ledger is used when none are given")]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(long, help = "Period to group by", default_value = "month")]
+        period: Period,
+        #[arg(short, long, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+        #[command(flatten)]
+        calculation: CalculationArgs,
+    },
+    /// Compare the spending of the current period against the category budgets
+    Budget {
+        #[arg(long, help = "Budget file", default_value = "budgets.toml")]
+        config: PathBuf,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(
+            long,
+            help = "Period to check as YYYY-MM or YYYY, defaults to the current one"
+        )]
+        period: Option<String>,
+    },
+    /// Show how the price of a product changed between purchases
+    History {
+        #[arg(help = "Product name, ignoring case")]
+        product: String,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(
+            long,
+            help = "Highlight increases above this percent, e.g. 10 for 10%",
+            default_value = "10"
+        )]
+        threshold: f64,
+        #[arg(short, long, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Estimate the cost of a basket at every place from the price history
+    Compare {
+        #[arg(help = "Basket file with the quantity of every product")]
+        basket: PathBuf,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(long, help = "Product catalog to resolve the basket names")]
+        catalog: Option<PathBuf>,
+        #[arg(short, long, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Pair the charges of bank statements with the imported receipts
+    Match {
+        #[arg(required = true, help = "OFX, QIF or CSV bank statements")]
+        statements: Vec<PathBuf>,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+        #[arg(
+            long,
+            help = "Largest number of days between a receipt and its charge",
+            default_value = "3"
+        )]
+        window: i64,
+        #[arg(
+            long,
+            help = "Largest tip added on the card terminal, in percent of the receipt, e.g. 25 for 25%",
+            default_value = "25"
+        )]
+        max_tip: f64,
+        #[arg(short, long, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Suggest categorization rules from already categorized products
+    SuggestRules {
+        #[arg(help = "Files to learn from, the ledger is used when none are given")]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Ledger database", default_value = "ledger.db")]
+        db: PathBuf,
+    },
+}
+
+/// Options that change how an invoice is computed.
+#[derive(clap::Args, Debug)]
+struct CalculationArgs {
+    #[arg(long, help = "Product catalog to map names to canonical products")]
+    catalog: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Store registry with place aliases and per-store defaults"
+    )]
+    stores: Option<PathBuf>,
+    #[arg(long, help = "Rules file to fill in or override product types")]
+    rules: Option<PathBuf>,
     #[arg(short, long, help = "Tips percentage")]
     tips_percentage: Option<f64>,
+    #[arg(
+        short,
+        long,
+        help = "Tax jurisdiction: mx, mx-ieps, es or us-<state>",
+        default_value = "mx",
+        value_parser = parse_jurisdiction
+    )]
+    jurisdiction: String,
+    #[arg(
+        long,
+        help = "Tax jurisdiction for a place, as PLACE=CODE",
+        value_parser = parse_place_jurisdiction
+    )]
+    place_jurisdiction: Vec<(String, String)>,
+    #[arg(
+        short,
+        long,
+        help = "Whether prices include taxes, defaults to the jurisdiction's mode"
+    )]
+    pricing: Option<PricingMode>,
+    #[arg(
+        long,
+        help = "Extract VAT as price * (1 - rate) to reproduce old reports",
+        default_value = "false"
+    )]
+    legacy_vat_math: bool,
+    #[arg(long, help = "Round taxes per line or on the invoice totals")]
+    rounding: Option<RoundingScope>,
+    #[arg(long, help = "How ties are rounded", default_value = "half-up")]
+    rounding_mode: RoundingMode,
+    #[arg(long, help = "Decimal places kept when rounding", default_value = "2")]
+    precision: u32,
+    #[arg(
+        long,
+        help = "Currency of amounts without a currency code or symbol",
+        default_value = currency::DEFAULT_CURRENCY
+    )]
+    currency: String,
+    #[arg(
+        long,
+        help = "Separators used by the amounts, guessed per file by default",
+        default_value = "auto"
+    )]
+    number_format: NumberFormat,
+    #[arg(
+        long,
+        help = "Read the files as free-text tickets, one item per line",
+        default_value = "false"
+    )]
+    ticket: bool,
+    #[arg(
+        long,
+        help = "Sheet to read from XLSX and ODS workbooks, the first by default"
+    )]
+    sheet: Option<String>,
+    #[arg(
+        long,
+        help = "Encoding of the files, detected when not given",
+        value_parser = input::parse_encoding
+    )]
+    encoding: Option<&'static Encoding>,
+    #[arg(long, help = "Exchange rates file", requires = "report_currency")]
+    rates: Option<PathBuf>,
+    #[arg(long, help = "Currency to convert the totals into", requires = "rates")]
+    report_currency: Option<String>,
+}
+
+fn parse_jurisdiction(code: &str) -> Result<String, String> {
+    match tax_engine::from_code(code) {
+        Some(_) => Ok(code.to_owned()),
+        None => Err(format!("unknown jurisdiction: {}", code)),
+    }
+}
+
+fn parse_place_jurisdiction(value: &str) -> Result<(String, String), String> {
+    let (place, code) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected PLACE=CODE, got: {}", value))?;
+    Ok((place.trim().to_owned(), parse_jurisdiction(code)?))
+}
+
+/// Picks the jurisdiction of the receipt's place: `--place-jurisdiction` first, then the
+/// store's tax profile, then `--jurisdiction`.
+fn jurisdiction_for<'a>(
+    args: &'a CalculationArgs,
+    stores: Option<&'a StoreRegistry>,
+    products: &[Product],
+) -> &'a str {
+    let place = products.first().map_or("", |x| x.place.trim());
+    args.place_jurisdiction
+        .iter()
+        .find(|(x, _)| x.eq_ignore_ascii_case(place))
+        .map(|(_, code)| code.as_str())
+        .or_else(|| {
+            stores
+                .and_then(|x| x.find(place))
+                .and_then(|x| x.tax_profile.as_deref())
+        })
+        .unwrap_or(args.jurisdiction.as_str())
 }
 
 fn clean_percentage(percentage: f64) -> f64 {
@@ -27,20 +327,670 @@ fn clean_percentage(percentage: f64) -> f64 {
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    let file = fs::read_to_string(args.file).unwrap();
-    let mut products: Vec<Product> = reader::read_file(&file);
+/// Reads a receipt file, stopping with an error when it cannot be read.
+fn read_input(path: &Path, encoding: Option<&'static Encoding>) -> String {
+    input::read_input(path, encoding).unwrap_or_else(|x| {
+        eprintln!("Cannot read {}: {}", path.display(), x);
+        std::process::exit(1);
+    })
+}
+
+/// Parses a configuration file, stopping with an error when it is missing or invalid.
+fn load_toml<T, E: std::fmt::Display>(
+    path: &Path,
+    kind: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> T {
+    let content = fs::read_to_string(path).unwrap_or_else(|x| {
+        eprintln!("Cannot read {} file {}: {}", kind, path.display(), x);
+        std::process::exit(1);
+    });
+    parse(&content).unwrap_or_else(|x| {
+        eprintln!("Invalid {} file {}: {}", kind, path.display(), x);
+        std::process::exit(1);
+    })
+}
+
+/// Opens the ledger, stopping with an error when it cannot be used. Only `import` creates
+/// a missing ledger; the other commands read an existing one.
+fn open_ledger(db: &Path, create: bool) -> Ledger {
+    if !create && !db.exists() {
+        eprintln!(
+            "Ledger {} does not exist, import some receipts first",
+            db.display()
+        );
+        std::process::exit(1);
+    }
+    let ledger = if create {
+        Ledger::open(db)
+    } else {
+        Ledger::open_existing(db)
+    };
+    ledger_result(db, ledger)
+}
+
+/// Unwraps a ledger operation, stopping with an error naming the ledger when it failed.
+fn ledger_result<T>(db: &Path, result: rusqlite::Result<T>) -> T {
+    result.unwrap_or_else(|x| {
+        eprintln!("Cannot use ledger {}: {}", db.display(), x);
+        std::process::exit(1);
+    })
+}
+
+/// Opens the ledger for a command that adds up its amounts, stopping with an error when they
+/// are in more than one currency.
+fn open_ledger_for_totals(db: &Path) -> Ledger {
+    let ledger = open_ledger(db, false);
+    let currencies = ledger_result(db, ledger.currencies());
+    if currencies.len() > 1 {
+        eprintln!(
+            "Cannot add up receipts in {} from {}, import them with --rates and --report-currency",
+            currencies.join(", "),
+            db.display()
+        );
+        std::process::exit(1);
+    }
+    ledger
+}
+
+/// The configuration files named by `CalculationArgs`, parsed once per run.
+struct Configuration {
+    catalog: Option<Catalog>,
+    stores: Option<StoreRegistry>,
+    rules: Option<Rules>,
+    rates: Option<ExchangeRates>,
+}
+
+impl Configuration {
+    fn load(args: &CalculationArgs) -> Self {
+        Configuration {
+            catalog: args
+                .catalog
+                .as_ref()
+                .map(|x| load_toml(x, "catalog", Catalog::from_toml)),
+            stores: args
+                .stores
+                .as_ref()
+                .map(|x| load_toml(x, "store", StoreRegistry::from_toml)),
+            rules: args
+                .rules
+                .as_ref()
+                .map(|x| load_toml(x, "rules", Rules::from_toml)),
+            rates: args
+                .rates
+                .as_ref()
+                .map(|x| load_toml(x, "exchange rates", ExchangeRates::from_toml)),
+        }
+    }
+}
+
+/// Reads the products of a free-text ticket, warning about the lines it does not understand
+/// and about totals that do not add up.
+fn read_ticket(path: &Path, args: &CalculationArgs) -> Vec<Product> {
+    let ticket = Ticket::parse(&read_input(path, args.encoding), args.number_format);
+    if !ticket.unrecognized.is_empty() {
+        eprintln!(
+            "Unrecognized lines in {}: {}",
+            path.display(),
+            ticket.unrecognized.join(" | ")
+        );
+    }
+    match ticket.verify() {
+        Verification::NoTotal => eprintln!("No total found in {} to verify", path.display()),
+        Verification::Mismatch(difference) => eprintln!(
+            "Total of {} is ${:0.2} but the items add up to ${:0.2}, off by ${:0.2}",
+            path.display(),
+            ticket.total.unwrap_or(0.0),
+            ticket.total.unwrap_or(0.0) - difference,
+            difference
+        ),
+        Verification::TaxesIncluded | Verification::TaxesAdded => {}
+    }
+    ticket.products()
+}
+
+/// Reads the products of a file, normalizing their places and mapping them to the catalog
+/// and categorizing them with the rules file when given.
+fn read_products(path: &Path, args: &CalculationArgs, config: &Configuration) -> Vec<Product> {
+    let mut products: Vec<Product> = if spreadsheet::is_workbook(path) {
+        spreadsheet::read_workbook(path, args.sheet.as_deref(), args.number_format).unwrap_or_else(
+            |x| {
+                eprintln!("Cannot read {}: {}", path.display(), x);
+                std::process::exit(1);
+            },
+        )
+    } else if args.ticket {
+        read_ticket(path, args)
+    } else {
+        let file = read_input(path, args.encoding);
+        reader::read_file_with_format(&file, args.number_format)
+    };
+    let mut currencies = Vec::new();
+    for product in products.iter_mut() {
+        let currency = product
+            .currency
+            .get_or_insert_with(|| args.currency.to_uppercase());
+        if !currencies.contains(currency) {
+            currencies.push(currency.clone());
+        }
+    }
+    if currencies.len() > 1 {
+        convert_products(path, &mut products, &currencies[0], config.rates.as_ref());
+    }
+    let unreadable = products
+        .iter()
+        .filter(|x| x.price.is_none())
+        .map(|x| x.product.as_str())
+        .collect::<Vec<_>>();
+    if !unreadable.is_empty() {
+        eprintln!(
+            "Unreadable prices in {}, counted as $0.00: {}",
+            path.display(),
+            unreadable.join(", ")
+        );
+    }
+    if let Some(stores) = &config.stores {
+        let unknown = stores.normalize_places(&mut products);
+        if !unknown.is_empty() {
+            eprintln!(
+                "Unknown places in {}: {}",
+                path.display(),
+                unknown.join(", ")
+            );
+        }
+    }
+    if let Some(catalog) = &config.catalog {
+        let unmatched = catalog.canonicalize(&mut products);
+        if !unmatched.is_empty() {
+            eprintln!(
+                "Products not in the catalog in {}: {}",
+                path.display(),
+                unmatched.join(", ")
+            );
+        }
+    }
+    if let Some(rules) = &config.rules {
+        let uncategorized = rules.categorize(&mut products);
+        if !uncategorized.is_empty() {
+            eprintln!(
+                "Uncategorized products in {}: {}",
+                path.display(),
+                uncategorized.join(", ")
+            );
+        }
+    }
+    products
+}
+
+/// Converts the products of a receipt that mixes currencies into `currency` at the rate of
+/// their date, stopping with an error when a rate is missing.
+fn convert_products(
+    path: &Path,
+    products: &mut [Product],
+    currency: &str,
+    rates: Option<&ExchangeRates>,
+) {
+    for product in products.iter_mut() {
+        let from = product.currency.clone().unwrap_or_default();
+        if from == currency {
+            continue;
+        }
+        let date = Date::parse(&product.date);
+        let Some(rate) = rates.and_then(|x| x.rate(&from, currency, date)) else {
+            eprintln!(
+                "Mixed currencies in {}: no exchange rate from {} to {} for {}, use --rates and --report-currency",
+                path.display(),
+                from,
+                currency,
+                date.map_or("unknown date".to_owned(), |x| x.to_string())
+            );
+            std::process::exit(1);
+        };
+        product.price = product.price.map(|x| x * rate);
+        product.currency = Some(currency.to_owned());
+    }
+}
+
+/// Builds the invoice with the requested options and computes its taxes.
+fn compute_invoice<'a>(
+    args: &CalculationArgs,
+    config: &Configuration,
+    products: &'a mut [Product],
+    verbose: bool,
+) -> Invoice<'a> {
+    let store = config
+        .stores
+        .as_ref()
+        .and_then(|x| x.find(products.first().map_or("", |x| x.place.as_str())))
+        .cloned();
+    let jurisdiction = jurisdiction_for(args, config.stores.as_ref(), products);
+    let mut engine = tax_engine::from_code(jurisdiction).unwrap();
+    if let Some(catalog) = &config.catalog {
+        engine = Box::new(catalog.with_tax_rules(engine));
+    }
     let products = products.iter_mut().collect::<Vec<&mut Product>>();
-    let mut invoice = Invoice::new(products);
+    let mut invoice = Invoice::new(products)
+        .with_engine(engine)
+        .with_legacy_vat_math(args.legacy_vat_math);
+    if let Some(store) = &store {
+        invoice = invoice.with_store(store);
+    }
+    if let Some(pricing) = args.pricing {
+        invoice = invoice.with_pricing_mode(pricing);
+    }
+    if let Some(scope) = args.rounding {
+        invoice = invoice.with_rounding(RoundingPolicy::new(
+            scope,
+            args.rounding_mode,
+            args.precision,
+        ));
+    }
+    if let (Some(rates), Some(report_currency)) = (&config.rates, &args.report_currency) {
+        let date = invoice.products().next().and_then(|x| Date::parse(&x.date));
+        let from = invoice.currency().to_owned();
+        let to = report_currency.to_uppercase();
+        match rates.rate(&from, &to, date) {
+            Some(rate) => invoice = invoice.with_exchange(&to, rate),
+            None => {
+                eprintln!(
+                    "No exchange rate from {} to {} for {}",
+                    from,
+                    to,
+                    date.map_or("unknown date".to_owned(), |x| x.to_string())
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    if verbose {
+        invoice.print_header();
+    }
 
     if let Some(tips_percentage) = args.tips_percentage {
         let tips_percentage = clean_percentage(tips_percentage);
-        println!("Adding tips from products: {}", tips_percentage);
+        if verbose {
+            println!("Adding tips from products: {}", tips_percentage);
+        }
         invoice.tips_from_products(tips_percentage);
     }
 
     invoice.calculate_taxes();
-    invoice.show_invoice(args.show_all);
-    invoice.print_resume();
+    invoice
+}
+
+fn import(files: &[PathBuf], db: &Path, skip_duplicates: bool, args: &CalculationArgs) {
+    let mut ledger = open_ledger(db, true);
+    let mut known = ledger_result(db, ledger.receipts());
+    let mut duplicates = 0;
+    let config = Configuration::load(args);
+    for path in files {
+        let mut products = read_products(path, args, &config);
+        if products.is_empty() {
+            println!("Skipping {}: no products", path.display());
+            continue;
+        }
+        let invoice = compute_invoice(args, &config, &mut products, false);
+        let source = path.display().to_string();
+        let mut receipt = Receipt::from_invoice(&source, &invoice);
+        let duplicate = match ledger_result(db, ledger.find_fingerprint(&receipt.fingerprint())) {
+            Some(original) => Some((original, DuplicateKind::Exact)),
+            None => receipt
+                .find_similar(&known)
+                .map(|x| (x.source.clone(), DuplicateKind::Similar)),
+        };
+        if let Some((original, kind)) = duplicate {
+            duplicates += 1;
+            let kind = match kind {
+                DuplicateKind::Exact => "duplicate",
+                DuplicateKind::Similar => "possible duplicate",
+            };
+            println!("Suspected {}: {} matches {}", kind, source, original);
+            if skip_duplicates {
+                println!("Skipping {}", source);
+                continue;
+            }
+        }
+        let id = ledger_result(db, ledger.save(&source, &invoice));
+        let converted = invoice.conversion().map_or(String::new(), |x| {
+            format!(" ({} ${:0.2})", x.currency, x.total)
+        });
+        println!(
+            "Imported {} as invoice {}: {} ${:0.2}{}",
+            source,
+            id,
+            invoice.currency(),
+            invoice.calculate_total(),
+            converted
+        );
+        receipt.source = format!("invoice {} ({})", id, source);
+        known.push(receipt);
+    }
+    if duplicates > 0 {
+        println!("{} suspected duplicate(s) found", duplicates);
+    }
+}
+
+fn query(by: Grouping, db: &Path, format: OutputFormat) {
+    let totals = ledger_result(db, open_ledger_for_totals(db).totals_by(by));
+    let title = format!("{:?}", by);
+    match format {
+        OutputFormat::Text => println!("{}", output::totals_to_text(&title, &totals)),
+        OutputFormat::Csv => println!("{}", output::totals_to_csv(&title, &totals)),
+        OutputFormat::Json => println!("{}", output::totals_to_json(&totals)),
+    }
+}
+
+fn report(
+    files: &[PathBuf],
+    db: &Path,
+    period: Period,
+    format: OutputFormat,
+    args: &CalculationArgs,
+) {
+    let (entries, taxes) = if files.is_empty() {
+        let ledger = open_ledger_for_totals(db);
+        (
+            ledger_result(db, ledger.entries()),
+            ledger_result(db, ledger.tax_entries()),
+        )
+    } else {
+        let config = Configuration::load(args);
+        let mut entries = Vec::new();
+        let mut taxes = Vec::new();
+        let mut currencies: Vec<String> = Vec::new();
+        for path in files {
+            let mut products = read_products(path, args, &config);
+            if products.is_empty() {
+                continue;
+            }
+            let invoice = compute_invoice(args, &config, &mut products, false);
+            let currency = invoice.report_currency();
+            if !currencies.iter().any(|x| x == currency) {
+                currencies.push(currency.to_owned());
+            }
+            let (invoice_entries, invoice_taxes) = report::entries_from_invoice(&invoice);
+            entries.extend(invoice_entries);
+            taxes.extend(invoice_taxes);
+        }
+        if currencies.len() > 1 {
+            eprintln!(
+                "Cannot add up receipts in {}, use --rates and --report-currency",
+                currencies.join(", ")
+            );
+            std::process::exit(1);
+        }
+        (entries, taxes)
+    };
+    let reports = report::build(&entries, &taxes, period);
+    match format {
+        OutputFormat::Text => println!("{}", output::report_to_text(&reports)),
+        OutputFormat::Csv => println!("{}", output::report_to_csv(&reports)),
+        OutputFormat::Json => println!("{}", output::report_to_json(&reports)),
+    }
+}
+
+/// Prints the budget status and exits with an error when any budget is exceeded.
+fn budget(config: &Path, db: &Path, period: Option<&str>) {
+    let budgets = load_toml(config, "budget", Budgets::from_toml);
+    let period = match period {
+        None => report::period_key(Some(Date::today()), budgets.period),
+        Some(x) => report::parse_period_key(x, budgets.period).unwrap_or_else(|| {
+            let expected = match budgets.period {
+                Period::Month => "YYYY-MM",
+                Period::Year => "YYYY",
+            };
+            eprintln!("Invalid period {}, expected {}", x, expected);
+            std::process::exit(1);
+        }),
+    };
+    let entries = ledger_result(db, open_ledger_for_totals(db).entries());
+    let statuses = budgets.check(&entries, &period);
+
+    let width = statuses
+        .iter()
+        .map(|x| x.category.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Category".len());
+    println!("Budgets for {}", period);
+    println!(
+        "{:<width$} {:>12} {:>12} {:>12}",
+        "Category", "Budget", "Spent", "Remaining"
+    );
+    for x in &statuses {
+        println!(
+            "{:<width$} {:>12.2} {:>12.2} {:>12.2}",
+            x.category,
+            x.budget,
+            x.spent,
+            x.remaining()
+        );
+    }
+
+    let exceeded = statuses.iter().filter(|x| x.exceeded()).collect::<Vec<_>>();
+    if !exceeded.is_empty() {
+        eprintln!("\nWarning: {} budget(s) exceeded", exceeded.len());
+        for x in exceeded {
+            eprintln!(
+                "  {}: spent ${:0.2} of ${:0.2}, over by ${:0.2}",
+                x.category,
+                x.spent,
+                x.budget,
+                -x.remaining()
+            );
+        }
+        std::process::exit(1);
+    }
+}
+
+fn export(
+    files: &[PathBuf],
+    format: JournalFormat,
+    accounts: Option<&Path>,
+    args: &CalculationArgs,
+) {
+    let accounts = accounts.map_or_else(Accounts::default, |x| {
+        load_toml(x, "accounts", Accounts::from_toml)
+    });
+    let config = Configuration::load(args);
+    let mut transactions = Vec::new();
+    for path in files {
+        let mut products = read_products(path, args, &config);
+        if products.is_empty() {
+            eprintln!("Skipping {}: no products", path.display());
+            continue;
+        }
+        let invoice = compute_invoice(args, &config, &mut products, false);
+        let source = path.display().to_string();
+        match journal::Transaction::from_invoice(&source, &invoice, &accounts) {
+            Ok(x) => transactions.push(x),
+            Err(error) => eprintln!("Skipping {}: {}", source, error),
+        }
+    }
+    println!("{}", journal::to_journal(&transactions, format));
+}
+
+fn history(product: &str, db: &Path, threshold: f64, format: OutputFormat) {
+    let purchases = ledger_result(db, open_ledger(db, false).purchases(product));
+    if purchases.is_empty() {
+        eprintln!("No purchases of {} found", product);
+        std::process::exit(1);
+    }
+    let threshold = threshold / 100.0;
+    let points = history::build(purchases, threshold);
+    match format {
+        OutputFormat::Text => println!("{}", output::history_to_text(product, &points, threshold)),
+        OutputFormat::Csv => println!("{}", output::history_to_csv(&points)),
+        OutputFormat::Json => println!("{}", output::history_to_json(&points)),
+    }
+}
+
+fn compare(basket: &Path, db: &Path, catalog: Option<&Path>, format: OutputFormat) {
+    let basket = load_toml(basket, "basket", Basket::from_toml);
+    let catalog = catalog.map(|x| load_toml(x, "catalog", Catalog::from_toml));
+    let ledger = open_ledger(db, false);
+    let purchases = basket
+        .items
+        .keys()
+        .map(|name| {
+            let canonical = catalog
+                .as_ref()
+                .and_then(|x| x.find(name))
+                .map_or(name.as_str(), |x| x.name.as_str());
+            (name.clone(), ledger_result(db, ledger.purchases(canonical)))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let estimates = basket.estimate(&purchases);
+    match format {
+        OutputFormat::Text => println!(
+            "{}",
+            output::estimates_to_text(basket.items.len(), &estimates)
+        ),
+        OutputFormat::Csv => println!("{}", output::estimates_to_csv(&estimates)),
+        OutputFormat::Json => println!("{}", output::estimates_to_json(&estimates)),
+    }
+}
+
+fn match_statements(
+    statements: &[PathBuf],
+    db: &Path,
+    window: i64,
+    max_tip: f64,
+    format: OutputFormat,
+) {
+    let mut transactions = Vec::new();
+    for path in statements {
+        let Some(statement_format) = StatementFormat::from_path(path) else {
+            eprintln!("Unknown statement format of {}", path.display());
+            std::process::exit(1);
+        };
+        match bank::read_statement(&read_input(path, None), statement_format) {
+            Ok(x) => transactions.extend(x),
+            Err(error) => {
+                eprintln!("Cannot read {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+        }
+    }
+    let receipts = ledger_result(db, open_ledger(db, false).receipts());
+    let reconciliation = reconcile::reconcile(&transactions, &receipts, window, max_tip / 100.0);
+    match format {
+        OutputFormat::Text => println!("{}", output::matches_to_text(&reconciliation)),
+        OutputFormat::Csv => println!("{}", output::matches_to_csv(&reconciliation.rows())),
+        OutputFormat::Json => println!("{}", output::matches_to_json(&reconciliation.rows())),
+    }
+}
+
+fn suggest_rules(files: &[PathBuf], db: &Path) {
+    let products = if files.is_empty() {
+        ledger_result(db, open_ledger(db, false).categorized_products())
+    } else {
+        files
+            .iter()
+            .flat_map(|x| reader::read_file(&read_input(x, None)))
+            .map(|x| (x.product, x.product_type))
+            .collect()
+    };
+    let suggestions = categorizer::suggest_rules(&products);
+    print!("{}", toml::to_string(&suggestions).unwrap());
+}
+
+fn main() {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Import {
+            files,
+            db,
+            skip_duplicates,
+            calculation,
+        }) => return import(files, db, *skip_duplicates, calculation),
+        Some(Command::Export {
+            files,
+            to,
+            accounts,
+            calculation,
+        }) => return export(files, *to, accounts.as_deref(), calculation),
+        Some(Command::Query { by, db, format }) => return query(*by, db, *format),
+        Some(Command::Report {
+            files,
+            db,
+            period,
+            format,
+            calculation,
+        }) => return report(files, db, *period, *format, calculation),
+        Some(Command::Budget { config, db, period }) => {
+            return budget(config, db, period.as_deref())
+        }
+        Some(Command::History {
+            product,
+            db,
+            threshold,
+            format,
+        }) => return history(product, db, *threshold, *format),
+        Some(Command::Compare {
+            basket,
+            db,
+            catalog,
+            format,
+        }) => return compare(basket, db, catalog.as_deref(), *format),
+        Some(Command::Match {
+            statements,
+            db,
+            window,
+            max_tip,
+            format,
+        }) => return match_statements(statements, db, *window, *max_tip, *format),
+        Some(Command::SuggestRules { files, db }) => return suggest_rules(files, db),
+        None => {}
+    }
+
+    let config = Configuration::load(&args.calculation);
+    let path = args.file.as_ref().unwrap();
+    let mut products = read_products(path, &args.calculation, &config);
+    if products.is_empty() {
+        eprintln!("No products in {}", path.display());
+        std::process::exit(1);
+    }
+    let invoice = compute_invoice(
+        &args.calculation,
+        &config,
+        &mut products,
+        args.format == InvoiceFormat::Text,
+    );
+    match args.format {
+        InvoiceFormat::Text => {
+            invoice.show_invoice(args.show_all);
+            if args.compare {
+                invoice.show_comparison();
+            }
+            if args.explain {
+                invoice.explain();
+            }
+            invoice.print_resume(args.sort_categories);
+        }
+        InvoiceFormat::Csv => {
+            println!(
+                "{}",
+                output::resume_to_csv(&invoice.resume(args.sort_categories))
+            );
+        }
+        InvoiceFormat::Json => {
+            println!(
+                "{}",
+                output::resume_to_json(&invoice.resume(args.sort_categories))
+            );
+        }
+        InvoiceFormat::Markdown => {
+            println!(
+                "{}",
+                output::invoice_to_markdown(&invoice.report(args.sort_categories))
+            );
+        }
+        InvoiceFormat::Html => {
+            println!(
+                "{}",
+                output::invoice_to_html(&invoice.report(args.sort_categories))
+            );
+        }
+    }
 }
@@ -0,0 +1,787 @@
+use crate::basket::StoreEstimate;
+use crate::date::Date;
+use crate::history::PricePoint;
+use crate::invoice::{InvoiceReport, Resume};
+use crate::ledger::LedgerTotal;
+use crate::reconcile::{MatchRow, Reconciliation};
+use crate::report::{Breakdown, PeriodReport};
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+/// Formats of the invoice, which besides the data formats can be rendered as a document.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InvoiceFormat {
+    Text,
+    Csv,
+    Json,
+    Markdown,
+    Html,
+}
+
+/// Quotes a CSV field when it contains separators, quotes or line breaks.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+pub fn resume_to_json(resume: &Resume) -> String {
+    serde_json::to_string_pretty(resume).unwrap()
+}
+
+/// One row per category followed by the invoice totals.
+pub fn resume_to_csv(resume: &Resume) -> String {
+    let mut lines = vec!["category,subtotal,tax,tip,total,share".to_owned()];
+    for x in &resume.categories {
+        lines.push(format!(
+            "{},{:.2},{:.2},{:.2},{:.2},{:.4}",
+            csv_field(&x.category),
+            x.subtotal,
+            x.tax,
+            x.tip,
+            x.total(),
+            x.share
+        ));
+    }
+    if resume.rounding != 0.0 {
+        lines.push(format!("Rounding,,,,{:.2},", resume.rounding));
+    }
+    lines.push(format!(
+        "Total,{:.2},{:.2},{:.2},{:.2},1.0000",
+        resume.products, resume.taxes, resume.tips, resume.total
+    ));
+    if let Some(x) = &resume.conversion {
+        lines.push(format!(
+            "Total {},{:.2},{:.2},{:.2},{:.2},",
+            x.currency, x.products, x.taxes, x.tips, x.total
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn totals_to_text(title: &str, totals: &[LedgerTotal]) -> String {
+    let key_width = totals
+        .iter()
+        .map(|x| x.key.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(title.len());
+    let mut lines = vec![format!(
+        "{:<key_width$} {:>8} {:>12} {:>12} {:>12} {:>12}",
+        title, "Invoices", "Subtotal", "Tax", "Tip", "Total"
+    )];
+    lines.push("-".repeat(key_width + 61));
+    for x in totals {
+        lines.push(format!(
+            "{:<key_width$} {:>8} {:>12.2} {:>12.2} {:>12.2} {:>12.2}",
+            x.key, x.invoices, x.subtotal, x.tax, x.tip, x.total
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn totals_to_csv(title: &str, totals: &[LedgerTotal]) -> String {
+    let mut lines = vec![format!(
+        "{},invoices,subtotal,tax,tip,total",
+        title.to_lowercase()
+    )];
+    for x in totals {
+        lines.push(format!(
+            "{},{},{:.2},{:.2},{:.2},{:.2}",
+            csv_field(&x.key),
+            x.invoices,
+            x.subtotal,
+            x.tax,
+            x.tip,
+            x.total
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn totals_to_json(totals: &[LedgerTotal]) -> String {
+    serde_json::to_string_pretty(totals).unwrap()
+}
+
+fn format_change(change: Option<f64>) -> String {
+    change.map_or("".to_owned(), |x| format!(" {:+.1}%", x * 100.0))
+}
+
+pub fn report_to_text(reports: &[PeriodReport]) -> String {
+    let mut lines = Vec::new();
+    for report in reports {
+        lines.push(format!(
+            "{}: ${:0.2} (products ${:0.2}, taxes ${:0.2}, tips ${:0.2}){}",
+            report.period,
+            report.total,
+            report.subtotal,
+            report.tax,
+            report.tip,
+            format_change(report.change)
+        ));
+        let sections: [(&str, &Vec<Breakdown>); 2] = [
+            ("Categories", &report.categories),
+            ("Places", &report.places),
+        ];
+        for (title, breakdowns) in sections {
+            lines.push(format!("  {}:", title));
+            for x in breakdowns {
+                lines.push(format!("    {:<24} {:>12.2}", x.key, x.total));
+            }
+        }
+        if !report.taxes.is_empty() {
+            lines.push("  Taxes:".to_owned());
+            for x in &report.taxes {
+                lines.push(format!("    {:<24} {:>12.2}", x.name, x.amount));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Flattens every period into rows of `period,dimension,key`, with the period totals
+/// under the `total` dimension.
+pub fn report_to_csv(reports: &[PeriodReport]) -> String {
+    let mut lines = vec!["period,dimension,key,subtotal,tax,tip,total,change".to_owned()];
+    for report in reports {
+        lines.push(format!(
+            "{},total,,{:.2},{:.2},{:.2},{:.2},{}",
+            report.period,
+            report.subtotal,
+            report.tax,
+            report.tip,
+            report.total,
+            report.change.map_or("".to_owned(), |x| format!("{:.4}", x))
+        ));
+        let sections: [(&str, &Vec<Breakdown>); 2] =
+            [("category", &report.categories), ("place", &report.places)];
+        for (dimension, breakdowns) in sections {
+            for x in breakdowns {
+                lines.push(format!(
+                    "{},{},{},{:.2},{:.2},{:.2},{:.2},",
+                    report.period,
+                    dimension,
+                    csv_field(&x.key),
+                    x.subtotal,
+                    x.tax,
+                    x.tip,
+                    x.total
+                ));
+            }
+        }
+        for x in &report.taxes {
+            lines.push(format!(
+                "{},tax,{},,{:.2},,{:.2},",
+                report.period,
+                csv_field(&x.name),
+                x.amount,
+                x.amount
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn report_to_json(reports: &[PeriodReport]) -> String {
+    serde_json::to_string_pretty(reports).unwrap()
+}
+
+fn format_optional(value: Option<f64>, precision: usize) -> String {
+    value.map_or(String::new(), |x| format!("{:.precision$}", x))
+}
+
+/// Purchases of a product, marking the increases above the threshold with `*`.
+pub fn history_to_text(product: &str, points: &[PricePoint], threshold: f64) -> String {
+    let place_width = points
+        .iter()
+        .map(|x| x.purchase.place.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Place".len());
+    let mut lines = vec![
+        format!("Price history of {}", product),
+        format!(
+            "  {:<10} {:<place_width$} {:>10} {:>10} {:>8} {:>10} {:>8}",
+            "Date", "Place", "Price", "Pre-tax", "Quantity", "Unit", "Change"
+        ),
+    ];
+    for x in points {
+        let line = format!(
+            "{} {:<10} {:<place_width$} {:>10.2} {:>10.2} {:>8} {:>10} {:>8}",
+            if x.increase { "*" } else { " " },
+            x.purchase.date,
+            x.purchase.place,
+            x.purchase.original_price,
+            x.purchase.price,
+            x.purchase.quantity.map_or(String::new(), |x| x.to_string()),
+            format_optional(x.unit_price, 2),
+            x.change
+                .map_or(String::new(), |x| format!("{:+.1}%", x * 100.0)),
+        );
+        lines.push(line.trim_end().to_owned());
+    }
+    if points.iter().any(|x| x.increase) {
+        lines.push(format!("* increase above {:.1}%", threshold * 100.0));
+    }
+    lines.join("\n")
+}
+
+pub fn history_to_csv(points: &[PricePoint]) -> String {
+    let mut lines =
+        vec!["date,place,original_price,price,quantity,unit_price,change,increase".to_owned()];
+    for x in points {
+        lines.push(format!(
+            "{},{},{:.2},{:.2},{},{},{},{}",
+            csv_field(&x.purchase.date),
+            csv_field(&x.purchase.place),
+            x.purchase.original_price,
+            x.purchase.price,
+            x.purchase.quantity.map_or(String::new(), |x| x.to_string()),
+            format_optional(x.unit_price, 2),
+            format_optional(x.change, 4),
+            x.increase
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn history_to_json(points: &[PricePoint]) -> String {
+    serde_json::to_string_pretty(points).unwrap()
+}
+
+/// Basket cost per place, naming the items a place has no price for.
+pub fn estimates_to_text(items: usize, estimates: &[StoreEstimate]) -> String {
+    let place_width = estimates
+        .iter()
+        .map(|x| x.place.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Place".len());
+    let mut lines = vec![format!(
+        "{:<place_width$} {:>12} {:>6} Missing",
+        "Place", "Cost", "Items"
+    )];
+    for x in estimates {
+        let line = format!(
+            "{:<place_width$} {:>12.2} {:>6} {}",
+            x.place,
+            x.cost,
+            format!("{}/{}", x.found, items),
+            x.missing.join(", ")
+        );
+        lines.push(line.trim_end().to_owned());
+    }
+    lines.join("\n")
+}
+
+pub fn estimates_to_csv(estimates: &[StoreEstimate]) -> String {
+    let mut lines = vec!["place,cost,found,missing".to_owned()];
+    for x in estimates {
+        lines.push(format!(
+            "{},{:.2},{},{}",
+            csv_field(&x.place),
+            x.cost,
+            x.found,
+            csv_field(&x.missing.join("; "))
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn estimates_to_json(estimates: &[StoreEstimate]) -> String {
+    serde_json::to_string_pretty(estimates).unwrap()
+}
+
+/// Matched charges first, then the charges and the receipts left without a pair.
+pub fn matches_to_text(reconciliation: &Reconciliation) -> String {
+    let mut lines = vec![format!("Matched ({})", reconciliation.matches.len())];
+    for x in &reconciliation.matches {
+        let tip = match x.tip > 0.0 {
+            true => format!(" (tip {:.2})", x.tip),
+            false => String::new(),
+        };
+        lines.push(format!(
+            "  {} {:>10.2} {} <- {} {} {:.2}{}",
+            x.transaction.date,
+            x.transaction.amount,
+            x.transaction.description,
+            x.receipt.source,
+            x.receipt.date,
+            x.receipt.total,
+            tip
+        ));
+    }
+    lines.push(format!(
+        "Unmatched charges ({})",
+        reconciliation.unmatched_charges.len()
+    ));
+    for x in &reconciliation.unmatched_charges {
+        let line = format!("  {} {:>10.2} {}", x.date, x.amount, x.description);
+        lines.push(line.trim_end().to_owned());
+    }
+    lines.push(format!(
+        "Unmatched receipts ({})",
+        reconciliation.unmatched_receipts.len()
+    ));
+    for x in &reconciliation.unmatched_receipts {
+        lines.push(format!("  {} {:>10.2} {}", x.date, x.total, x.source));
+    }
+    lines.join("\n")
+}
+
+pub fn matches_to_csv(rows: &[MatchRow]) -> String {
+    let mut lines = vec![
+        "status,date,amount,description,receipt,receipt_date,receipt_total,tip,days".to_owned(),
+    ];
+    for x in rows {
+        lines.push(format!(
+            "{},{},{},{},{},{},{},{},{}",
+            x.status,
+            x.date.as_deref().unwrap_or(""),
+            format_optional(x.amount, 2),
+            csv_field(x.description.as_deref().unwrap_or("")),
+            csv_field(x.receipt.as_deref().unwrap_or("")),
+            csv_field(x.receipt_date.as_deref().unwrap_or("")),
+            format_optional(x.receipt_total, 2),
+            format_optional(x.tip, 2),
+            x.days.map_or(String::new(), |x| x.to_string())
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn matches_to_json(rows: &[MatchRow]) -> String {
+    serde_json::to_string_pretty(rows).unwrap()
+}
+
+/// Title, subtitle and the rows of every table of an invoice document. Amounts are
+/// formatted, and the totals leave out the tips and rounding when there are none.
+struct Document {
+    title: String,
+    subtitle: String,
+    products: Vec<[String; 4]>,
+    categories: Vec<[String; 6]>,
+    taxes: Vec<[String; 2]>,
+    totals: Vec<[String; 2]>,
+}
+
+const PRODUCT_HEADER: [&str; 4] = ["Product", "Category", "Original", "Price"];
+const CATEGORY_HEADER: [&str; 6] = ["Category", "Subtotal", "Tax", "Tip", "Total", "Share"];
+const TAX_HEADER: [&str; 2] = ["Tax", "Amount"];
+const TOTAL_HEADER: [&str; 2] = ["", "Amount"];
+
+fn money(value: f64) -> String {
+    format!("${:0.2}", if value.abs() < 0.005 { 0.0 } else { value })
+}
+
+impl Document {
+    fn new(report: &InvoiceReport) -> Self {
+        let resume = &report.resume;
+        let first = report.lines.first();
+        let date = first.map_or(String::new(), |x| {
+            Date::parse(&x.date).map_or(x.date.clone(), |x| x.to_string())
+        });
+        let title = match (first.map_or("", |x| x.place.as_str()), date.as_str()) {
+            ("", "") => "Invoice".to_owned(),
+            (place, "") | ("", place) => format!("Invoice: {}", place),
+            (place, date) => format!("Invoice: {}, {}", place, date),
+        };
+        let subtitle = format!(
+            "{}, {} prices, {}",
+            resume.engine, resume.pricing, resume.currency
+        );
+        let mut totals = vec![["Products".to_owned(), money(resume.products)]];
+        if resume.tips != 0.0 {
+            totals.push(["Tips".to_owned(), money(resume.tips)]);
+        }
+        totals.push(["Taxes".to_owned(), money(resume.taxes)]);
+        if resume.rounding != 0.0 {
+            totals.push(["Rounding".to_owned(), money(resume.rounding)]);
+        }
+        totals.push(["Total".to_owned(), money(resume.total)]);
+        if let Some(x) = &resume.conversion {
+            totals.push([format!("Total {}", x.currency), money(x.total)]);
+        }
+        Document {
+            title,
+            subtitle,
+            products: report
+                .lines
+                .iter()
+                .map(|x| {
+                    [
+                        x.product.clone(),
+                        x.category.clone(),
+                        money(x.original),
+                        money(x.price),
+                    ]
+                })
+                .collect(),
+            categories: resume
+                .categories
+                .iter()
+                .map(|x| {
+                    [
+                        x.category.clone(),
+                        money(x.subtotal),
+                        money(x.tax),
+                        money(x.tip),
+                        money(x.total()),
+                        format!("{:0.1}%", x.share * 100.0),
+                    ]
+                })
+                .collect(),
+            taxes: report
+                .taxes
+                .iter()
+                .map(|x| [x.product.clone(), money(x.price)])
+                .collect(),
+            totals,
+        }
+    }
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// A pipe table, with the columns after the first `text` ones aligned right.
+fn markdown_table<const N: usize>(header: [&str; N], text: usize, rows: &[[String; N]]) -> String {
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let mut lines = vec![
+        line(header.iter().map(|x| markdown_cell(x)).collect()),
+        line(
+            (0..N)
+                .map(|i| if i < text { "---" } else { "---:" }.to_owned())
+                .collect(),
+        ),
+    ];
+    for row in rows {
+        lines.push(line(row.iter().map(|x| markdown_cell(x)).collect()));
+    }
+    lines.join("\n")
+}
+
+pub fn invoice_to_markdown(report: &InvoiceReport) -> String {
+    let document = Document::new(report);
+    let mut sections = vec![
+        format!("# {}", markdown_cell(&document.title)),
+        markdown_cell(&document.subtitle),
+        format!(
+            "## Products\n\n{}",
+            markdown_table(PRODUCT_HEADER, 2, &document.products)
+        ),
+    ];
+    if !document.categories.is_empty() {
+        sections.push(format!(
+            "## Categories\n\n{}",
+            markdown_table(CATEGORY_HEADER, 1, &document.categories)
+        ));
+    }
+    if !document.taxes.is_empty() {
+        sections.push(format!(
+            "## Taxes\n\n{}",
+            markdown_table(TAX_HEADER, 1, &document.taxes)
+        ));
+    }
+    sections.push(format!(
+        "## Totals\n\n{}",
+        markdown_table(TOTAL_HEADER, 1, &document.totals)
+    ));
+    sections.join("\n\n")
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_table<const N: usize>(header: [&str; N], text: usize, rows: &[[String; N]]) -> String {
+    let row = |tag: &str, cells: Vec<&str>| {
+        let cells = cells
+            .iter()
+            .enumerate()
+            .map(|(i, x)| match i < text {
+                true => format!("<{tag} class=\"text\">{}</{tag}>", escape_html(x)),
+                false => format!("<{tag}>{}</{tag}>", escape_html(x)),
+            })
+            .collect::<String>();
+        format!("<tr>{}</tr>", cells)
+    };
+    let mut lines = vec![
+        "<table>".to_owned(),
+        format!("<thead>{}</thead>", row("th", header.to_vec())),
+        "<tbody>".to_owned(),
+    ];
+    for x in rows {
+        lines.push(row("td", x.iter().map(String::as_str).collect()));
+    }
+    lines.push("</tbody>".to_owned());
+    lines.push("</table>".to_owned());
+    lines.join("\n")
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em auto; max-width: 48em; color: #222; }
+h1 { font-size: 1.5em; margin-bottom: 0.2em; }
+h2 { font-size: 1.1em; margin-top: 1.5em; }
+p { color: #555; margin-top: 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; text-align: right; }
+.text { text-align: left; }
+th { border-bottom: 2px solid #222; }
+@media print { body { margin: 0; max-width: none; } h2, table { break-inside: avoid; } }";
+
+/// A standalone page, with its styles inline so it can be shared as a single file and
+/// printed as it is.
+pub fn invoice_to_html(report: &InvoiceReport) -> String {
+    let document = Document::new(report);
+    let title = escape_html(&document.title);
+    let mut lines = vec![
+        "<!DOCTYPE html>".to_owned(),
+        "<html>".to_owned(),
+        "<head>".to_owned(),
+        "<meta charset=\"utf-8\">".to_owned(),
+        format!("<title>{}</title>", title),
+        format!("<style>\n{}\n</style>", HTML_STYLE),
+        "</head>".to_owned(),
+        "<body>".to_owned(),
+        format!("<h1>{}</h1>", title),
+        format!("<p>{}</p>", escape_html(&document.subtitle)),
+        "<h2>Products</h2>".to_owned(),
+        html_table(PRODUCT_HEADER, 2, &document.products),
+    ];
+    if !document.categories.is_empty() {
+        lines.push("<h2>Categories</h2>".to_owned());
+        lines.push(html_table(CATEGORY_HEADER, 1, &document.categories));
+    }
+    if !document.taxes.is_empty() {
+        lines.push("<h2>Taxes</h2>".to_owned());
+        lines.push(html_table(TAX_HEADER, 1, &document.taxes));
+    }
+    lines.push("<h2>Totals</h2>".to_owned());
+    lines.push(html_table(TOTAL_HEADER, 1, &document.totals));
+    lines.push("</body>".to_owned());
+    lines.push("</html>".to_owned());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Purchase;
+    use crate::invoice::{CategoryTotal, Conversion, InvoiceLine};
+
+    fn resume() -> Resume {
+        Resume {
+            engine: "Mexico (IVA)".to_owned(),
+            pricing: "tax-inclusive".to_owned(),
+            products: 100.0,
+            tips: 0.0,
+            taxes: 16.0,
+            rounding: 0.0,
+            total: 116.0,
+            categories: vec![CategoryTotal {
+                category: "Comida, bebida".to_owned(),
+                subtotal: 100.0,
+                tax: 16.0,
+                tip: 0.0,
+                share: 1.0,
+            }],
+            currency: "MXN".to_owned(),
+            conversion: None,
+        }
+    }
+
+    #[test]
+    fn test_resume_to_csv() {
+        let csv = resume_to_csv(&resume());
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "\"Comida, bebida\",100.00,16.00,0.00,116.00,1.0000"
+        );
+        assert_eq!(lines[2], "Total,100.00,16.00,0.00,116.00,1.0000");
+
+        let mut resume = resume();
+        resume.conversion = Some(Conversion {
+            currency: "USD".to_owned(),
+            rate: 0.05,
+            products: 5.0,
+            tips: 0.0,
+            taxes: 0.8,
+            total: 5.8,
+        });
+        let csv = resume_to_csv(&resume);
+        assert_eq!(csv.lines().last(), Some("Total USD,5.00,0.80,0.00,5.80,"));
+    }
+
+    #[test]
+    fn test_resume_to_json() {
+        let json: serde_json::Value = serde_json::from_str(&resume_to_json(&resume())).unwrap();
+        assert_eq!(json["total"], 116.0);
+        assert_eq!(json["categories"][0]["category"], "Comida, bebida");
+    }
+
+    #[test]
+    fn test_totals_to_csv() {
+        let totals = [LedgerTotal {
+            key: "2024-12".to_owned(),
+            invoices: 2,
+            subtotal: 100.0,
+            tax: 16.0,
+            tip: 10.0,
+            total: 126.0,
+        }];
+        assert_eq!(
+            totals_to_csv("Month", &totals),
+            "month,invoices,subtotal,tax,tip,total\n2024-12,2,100.00,16.00,10.00,126.00"
+        );
+        assert_eq!(totals_to_text("Month", &totals).lines().count(), 3);
+    }
+
+    #[test]
+    fn test_report_to_csv() {
+        let reports = [PeriodReport {
+            period: "2025-01".to_owned(),
+            subtotal: 100.0,
+            tax: 16.0,
+            tip: 0.0,
+            total: 116.0,
+            change: Some(0.5),
+            categories: vec![Breakdown {
+                key: "Alcohol".to_owned(),
+                subtotal: 100.0,
+                tax: 16.0,
+                tip: 0.0,
+                total: 116.0,
+            }],
+            places: vec![],
+            taxes: vec![],
+        }];
+        let csv = report_to_csv(&reports);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "2025-01,total,,100.00,16.00,0.00,116.00,0.5000");
+        assert_eq!(
+            lines[2],
+            "2025-01,category,Alcohol,100.00,16.00,0.00,116.00,"
+        );
+        assert!(report_to_text(&reports).contains("+50.0%"));
+    }
+
+    #[test]
+    fn test_history() {
+        let points = crate::history::build(
+            vec![
+                Purchase {
+                    date: "2025-01-03".to_owned(),
+                    place: "Oxxo".to_owned(),
+                    original_price: 20.0,
+                    price: 17.24,
+                    tax: 2.76,
+                    quantity: None,
+                },
+                Purchase {
+                    date: "2025-02-01".to_owned(),
+                    place: "Oxxo".to_owned(),
+                    original_price: 75.0,
+                    price: 64.66,
+                    tax: 10.34,
+                    quantity: Some(3.0),
+                },
+            ],
+            0.1,
+        );
+        let text = history_to_text("Cerveza", &points, 0.1);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].starts_with("* 2025-02-01"));
+        assert!(lines[3].ends_with("+25.0%"));
+        assert_eq!(lines[2], "  2025-01-03 Oxxo       20.00      17.24");
+        assert_eq!(lines[4], "* increase above 10.0%");
+
+        let csv = history_to_csv(&points);
+        assert_eq!(
+            csv.lines().nth(2),
+            Some("2025-02-01,Oxxo,75.00,64.66,3,25.00,0.2500,true")
+        );
+    }
+
+    #[test]
+    fn test_estimates() {
+        let estimates = [
+            StoreEstimate {
+                place: "Walmart".to_owned(),
+                cost: 250.0,
+                found: 2,
+                missing: Vec::new(),
+            },
+            StoreEstimate {
+                place: "Oxxo".to_owned(),
+                cost: 132.0,
+                found: 1,
+                missing: vec!["Vino Tinto".to_owned()],
+            },
+        ];
+        let text = estimates_to_text(2, &estimates);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "Walmart       250.00    2/2");
+        assert_eq!(lines[2], "Oxxo          132.00    1/2 Vino Tinto");
+        assert_eq!(
+            estimates_to_csv(&estimates).lines().nth(2),
+            Some("Oxxo,132.00,1,Vino Tinto")
+        );
+    }
+
+    fn report() -> InvoiceReport {
+        let line = |product: &str, category: &str, original: f64, price: f64| InvoiceLine {
+            date: "2024-12-27".to_owned(),
+            product: product.to_owned(),
+            category: category.to_owned(),
+            place: "Bar & Grill".to_owned(),
+            original,
+            price,
+        };
+        InvoiceReport {
+            lines: vec![line("Tacos | 3", "Comida, bebida", 116.0, 100.0)],
+            taxes: vec![line("IVA", "Impuestos", 16.0, 16.0)],
+            resume: resume(),
+        }
+    }
+
+    #[test]
+    fn test_invoice_to_markdown() {
+        let markdown = invoice_to_markdown(&report());
+        let lines = markdown.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Invoice: Bar & Grill, 2024-12-27");
+        assert_eq!(lines[2], "Mexico (IVA), tax-inclusive prices, MXN");
+        assert!(lines.contains(&"| Product | Category | Original | Price |"));
+        assert!(lines.contains(&"| --- | --- | ---: | ---: |"));
+        assert!(lines.contains(&"| Tacos \\| 3 | Comida, bebida | $116.00 | $100.00 |"));
+        assert!(lines.contains(&"| IVA | $16.00 |"));
+        assert!(lines.contains(&"| Total | $116.00 |"));
+        assert!(!markdown.contains("Tips"));
+    }
+
+    #[test]
+    fn test_invoice_to_html() {
+        let html = invoice_to_html(&report());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Invoice: Bar &amp; Grill, 2024-12-27</title>"));
+        assert!(html.contains("@media print"));
+        assert!(html.contains("<tr><td class=\"text\">Total</td><td>$116.00</td></tr>"));
+        assert!(!html.contains("<link") && !html.contains("<script"));
+    }
+}
@@ -1,3 +1,12 @@
+/// A change made to a product price while computing the invoice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustment {
+    pub(crate) step: &'static str,
+    pub(crate) before: f64,
+    pub(crate) after: f64,
+    pub(crate) reason: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Product {
     pub(crate) date: String,
@@ -5,9 +14,45 @@ pub struct Product {
     pub(crate) product_type: String,
     pub(crate) place: String,
     pub(crate) price: Option<f64>,
+    /// Units bought, when the receipt states them.
+    pub(crate) quantity: Option<f64>,
+    /// ISO code of the amount's currency, when the receipt names one.
+    pub(crate) currency: Option<String>,
+    pub(crate) adjustments: Vec<Adjustment>,
 }
 
 impl Product {
+    /// Replaces the price, keeping a record of the step that changed it.
+    pub(crate) fn adjust_price(&mut self, step: &'static str, price: f64, reason: String) {
+        self.adjustments.push(Adjustment {
+            step,
+            before: self.price.unwrap_or(0.0),
+            after: price,
+            reason,
+        });
+        self.price = Some(price);
+    }
+
+    /// Price as it was read from the receipt, before any adjustment.
+    pub(crate) fn original_price(&self) -> f64 {
+        self.adjustments
+            .first()
+            .map_or(self.price.unwrap_or(0.0), |x| x.before)
+    }
+
+    pub(crate) fn explain(&self) {
+        println!("{} ({})", self.product, self.product_type);
+        if self.adjustments.is_empty() {
+            println!("    ${:0.2} unchanged", self.price.unwrap_or(0.0));
+        }
+        for x in &self.adjustments {
+            println!(
+                "    {}: ${:0.2} -> ${:0.2}, {}",
+                x.step, x.before, x.after, x.reason
+            );
+        }
+    }
+
     pub(crate) fn show(&self) {
         println!("{:0.2}", self.price.unwrap_or(0.0));
     }
@@ -33,6 +78,9 @@ impl Product {
             product_type: product_type.to_owned(),
             place: product.place.clone(),
             price,
+            quantity: None,
+            currency: product.currency.clone(),
+            adjustments: Vec::new(),
         }
     }
 }
@@ -100,7 +148,7 @@ pub fn calculate_total_from_products_mut(products: &Vec<&mut Product>) -> f64 {
         .fold(0.0, |acc, x| acc + x.price.unwrap_or(0.0))
 }
 
-pub fn calculate_total_from_products(products: &Vec<Product>) -> f64 {
+pub fn calculate_total_from_products(products: &[Product]) -> f64 {
     products
         .iter()
         .fold(0.0, |acc, x| acc + x.price.unwrap_or(0.0))
@@ -156,6 +204,7 @@ mod tests {
     // }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_extract_by_type_mut() {
         let mut products = vec![
             Product {
@@ -164,6 +213,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -171,6 +223,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -178,6 +233,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -185,6 +243,9 @@ mod tests {
                 product_type: "Bebida".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
             Product {
                 date: "2021-01-01".to_owned(),
@@ -192,6 +253,9 @@ mod tests {
                 product_type: "Impuestos".to_owned(),
                 place: "Bar".to_owned(),
                 price: Some(2.0),
+                quantity: None,
+                currency: None,
+                adjustments: Vec::new(),
             },
         ];
         let mut products = products.iter_mut().collect::<Vec<_>>();
@@ -200,4 +264,24 @@ mod tests {
         assert_eq!(beverages.unwrap().len(), 4);
         assert_eq!(products.len(), 1);
     }
+
+    #[test]
+    fn test_adjust_price() {
+        let mut product = Product {
+            date: "2021-01-01".to_owned(),
+            product: "Cerveza".to_owned(),
+            product_type: "Bebida".to_owned(),
+            place: "Bar".to_owned(),
+            price: Some(116.0),
+            quantity: None,
+            currency: None,
+            adjustments: Vec::new(),
+        };
+        product.adjust_price("remove VAT", 100.0, "IVA 16%".to_owned());
+        assert_eq!(product.price, Some(100.0));
+        assert_eq!(product.adjustments.len(), 1);
+        assert_eq!(product.adjustments[0].before, 116.0);
+        assert_eq!(product.adjustments[0].after, 100.0);
+        assert_eq!(product.original_price(), 116.0);
+    }
 }
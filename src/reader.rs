@@ -1,45 +1,323 @@
+use crate::amount::{self, NumberFormat};
+use crate::currency;
+use crate::date::Date;
 use crate::product::Product;
 
-pub fn read_file(file: &str) -> Vec<Product> {
-    file.split("\n")
-        .into_iter()
-        .map(|x| x.split("\t"))
-        .filter(|x| x.clone().count() >= 5)
-        .map(|mut x| {
-            let date = x.next().unwrap();
-            let product = x.next().unwrap();
-            let product_type = x.next().unwrap();
-            let place = x.next().unwrap();
-            let price = x
-                .next()
-                .unwrap()
-                .replace("$", "")
-                .replace(",", "")
-                .trim()
-                .to_owned()
-                .parse::<f64>()
-                .ok();
-
-            Product {
-                date: date.to_owned(),
-                product: product.to_owned(),
-                product_type: product_type.to_owned(),
-                place: place.to_owned(),
-                price,
-            }
+const DATE_NAMES: [&str; 4] = ["date", "fecha", "day", "dia"];
+const PRODUCT_NAMES: [&str; 9] = [
+    "product",
+    "producto",
+    "name",
+    "nombre",
+    "item",
+    "articulo",
+    "description",
+    "descripcion",
+    "concepto",
+];
+const TYPE_NAMES: [&str; 5] = ["type", "product type", "category", "tipo", "categoria"];
+const PLACE_NAMES: [&str; 6] = ["place", "store", "shop", "lugar", "tienda", "comercio"];
+const PRICE_NAMES: [&str; 6] = ["price", "amount", "precio", "importe", "monto", "total"];
+const QUANTITY_NAMES: [&str; 6] = ["quantity", "qty", "cantidad", "cant", "units", "unidades"];
+const CURRENCY_NAMES: [&str; 3] = ["currency", "moneda", "divisa"];
+
+/// A table cell. Spreadsheets keep their numbers and dates, text files only have text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+    Date(Date),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Text(x) => x.clone(),
+            Cell::Number(x) => x.to_string(),
+            Cell::Date(x) => x.to_string(),
+        }
+    }
+
+    fn amount(&self, format: NumberFormat) -> Option<f64> {
+        match self {
+            Cell::Text(x) => amount::parse(x, format),
+            Cell::Number(x) => Some(*x),
+            Cell::Date(_) => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Cell::Text(x) if x.trim().is_empty())
+    }
+}
+
+/// Lowercase, without accents and with single spaces, so "JABÓN " matches "Jabon". Names of
+/// products, places and aliases are all compared this way.
+pub fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .map(|x| match x {
+            'á' | 'à' | 'ä' | 'â' => 'a',
+            'é' | 'è' | 'ë' | 'ê' => 'e',
+            'í' | 'ì' | 'ï' | 'î' => 'i',
+            'ó' | 'ò' | 'ö' | 'ô' => 'o',
+            'ú' | 'ù' | 'ü' | 'û' => 'u',
+            _ => x,
+        })
+        .collect()
+}
+
+/// Header name normalized, also taking underscores and dashes as spaces.
+pub fn header_key(value: &str) -> String {
+    normalize(&value.replace(['_', '-'], " "))
+}
+
+/// Where every product field is in a table row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    date: Option<usize>,
+    product: usize,
+    product_type: Option<usize>,
+    place: Option<usize>,
+    price: usize,
+    quantity: Option<usize>,
+    currency: Option<usize>,
+    /// Whether a quantity and a currency may follow the price in any order.
+    trailing: bool,
+}
+
+impl Columns {
+    /// Date, product, type, place and price, in this order.
+    pub fn positional() -> Self {
+        Columns {
+            date: Some(0),
+            product: 1,
+            product_type: Some(2),
+            place: Some(3),
+            price: 4,
+            quantity: None,
+            currency: None,
+            trailing: true,
+        }
+    }
+
+    /// Maps a header row by the names of its cells, which need at least a product and a
+    /// price column.
+    pub fn from_header(cells: &[Cell]) -> Option<Self> {
+        let find = |names: &[&str]| {
+            cells.iter().position(|x| match x {
+                Cell::Text(x) => names.contains(&header_key(x).as_str()),
+                _ => false,
+            })
+        };
+        Some(Columns {
+            date: find(&DATE_NAMES),
+            product: find(&PRODUCT_NAMES)?,
+            product_type: find(&TYPE_NAMES),
+            place: find(&PLACE_NAMES),
+            price: find(&PRICE_NAMES)?,
+            quantity: find(&QUANTITY_NAMES),
+            currency: find(&CURRENCY_NAMES),
+            trailing: false,
         })
+    }
+
+    fn fits(&self, cells: &[Cell]) -> bool {
+        let last = match self.trailing {
+            true => 4,
+            false => self.product.max(self.price),
+        };
+        cells.len() > last
+    }
+
+    fn product(&self, cells: &[Cell], format: NumberFormat) -> Product {
+        let text = |x: Option<usize>| {
+            x.and_then(|x| cells.get(x))
+                .map_or(String::new(), |x| x.text().trim().to_owned())
+        };
+        let raw_price = text(Some(self.price));
+        let price = cells[self.price].amount(format);
+        let mut quantity = self
+            .quantity
+            .and_then(|x| cells.get(x))
+            .and_then(|x| x.amount(format));
+        let mut currency = currency::detect(&raw_price).or(currency::detect(&text(self.currency)));
+        if self.trailing {
+            for cell in &cells[self.price + 1..] {
+                match cell {
+                    Cell::Number(x) => quantity = Some(*x),
+                    x => match x.text().trim().parse::<f64>() {
+                        Ok(value) => quantity = Some(value),
+                        Err(_) => currency = currency.or(currency::detect(&x.text())),
+                    },
+                }
+            }
+        }
+
+        Product {
+            date: text(self.date),
+            product: text(Some(self.product)),
+            product_type: text(self.product_type),
+            place: text(self.place),
+            price,
+            quantity,
+            currency: currency.map(|x| x.to_owned()),
+            adjustments: Vec::new(),
+        }
+    }
+}
+
+/// Reads the products of a table, using the first row as header when it names the columns
+/// and the positional layout otherwise. `Auto` picks a single number format for the whole
+/// table from all its text prices.
+pub fn read_rows(rows: &[Vec<Cell>], format: NumberFormat) -> Vec<Product> {
+    let (columns, rows) = match rows.first().and_then(|x| Columns::from_header(x)) {
+        Some(columns) => (columns, &rows[1..]),
+        None => (Columns::positional(), rows),
+    };
+    let rows = rows
+        .iter()
+        .filter(|x| columns.fits(x) && !x.iter().all(Cell::is_empty))
+        .collect::<Vec<_>>();
+    let format = match format {
+        NumberFormat::Auto => amount::detect(rows.iter().filter_map(|x| match &x[columns.price] {
+            Cell::Text(x) => Some(x.as_str()),
+            _ => None,
+        })),
+        x => x,
+    };
+    rows.into_iter()
+        .map(|x| columns.product(x, format))
         .collect()
 }
 
+pub fn read_file(file: &str) -> Vec<Product> {
+    read_file_with_format(file, NumberFormat::Auto)
+}
+
+/// Reads the tab separated products of a text file.
+///
+/// Pasted blocks are accepted as they come: Windows line endings, the indentation before
+/// the first column and the spaces around every cell are ignored.
+pub fn read_file_with_format(file: &str, format: NumberFormat) -> Vec<Product> {
+    let rows = file
+        .lines()
+        .map(|x| {
+            x.trim_matches(' ')
+                .split('\t')
+                .map(|x| Cell::Text(x.trim().to_owned()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    read_rows(&rows, format)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  JABÓN   Zote "), "jabon zote");
+        assert_eq!(header_key("Fecha_de-Operación"), "fecha de operacion");
+    }
+
     #[test]
     fn test_read_file() {
         let file = "2021-01-01\tCerveza\tBebida\tBar\t$2.00\n2021-01-01\tCerveza\tBebida\tBar\t$2.00\n2021-01-01\tCerveza\tBebida\tBar\t$2.00\n2021-01-01\tCerveza\tBebida\tBar\t$2.00\n2021-01-01\tCerveza\tBebida\tBar\t$2.00\n";
         let products = read_file(file);
         assert_eq!(products.len(), 5);
+        assert_eq!(products[0].quantity, None);
+    }
+
+    #[test]
+    fn test_read_file_quantity() {
+        let products = read_file("2021-01-01\tCerveza\tBebida\tBar\t$6.00\t3\n");
+        assert_eq!(products[0].price, Some(6.0));
+        assert_eq!(products[0].quantity, Some(3.0));
+    }
+
+    #[test]
+    fn test_read_file_currency() {
+        let products = read_file(
+            "2021-01-01\tBeer\tBebida\tBar\tUS$6.00\n\
+             2021-01-01\tCerveza\tBebida\tBar\t$6.00\n\
+             2021-01-01\tBier\tBebida\tBar\t6.00 €\t2\n\
+             2021-01-01\tBeer\tBebida\tPub\t$6.00\tGBP\t2\n",
+        );
+        assert_eq!(products[0].currency.as_deref(), Some("USD"));
+        assert_eq!(products[0].price, Some(6.0));
+        assert_eq!(products[1].currency, None);
+        assert_eq!(products[2].currency.as_deref(), Some("EUR"));
+        assert_eq!(products[2].quantity, Some(2.0));
+        assert_eq!(products[3].currency.as_deref(), Some("GBP"));
+        assert_eq!(products[3].quantity, Some(2.0));
+    }
+
+    #[test]
+    fn test_read_file_number_format() {
+        let file = "2021-01-01\tVino\tAlcohol\tBar\t1.234,50 €\n\
+                    2021-01-01\tAgua\tBebida\tBar\t2,00 €\n\
+                    2021-01-01\tDescuento\tOcio\tBar\t(1.000)\n";
+        let products = read_file(file);
+        assert_eq!(products[0].price, Some(1234.5));
+        assert_eq!(products[1].price, Some(2.0));
+        assert_eq!(products[2].price, Some(-1000.0));
+
+        let products = read_file_with_format(file, NumberFormat::Point);
+        assert_eq!(products[1].price, Some(200.0));
+    }
+
+    #[test]
+    fn test_read_file_pasted() {
+        let products = read_file(
+            "  2021-01-01\tCerveza \t Bebida\tBar\t$2.00 \r\n\r\n    2021-01-02\tAgua\tBebida\tBar\t$1.00\t2\r\n",
+        );
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].date, "2021-01-01");
+        assert_eq!(products[0].product, "Cerveza");
+        assert_eq!(products[0].product_type, "Bebida");
+        assert_eq!(products[0].price, Some(2.0));
+        assert_eq!(products[1].quantity, Some(2.0));
+    }
+
+    #[test]
+    fn test_read_file_header() {
+        let products = read_file(
+            "Fecha\tTienda\tDescripción\tImporte\tCantidad\tMoneda\n\
+             2021-01-01\tPub\tBeer\t12.50\t2\tUSD\n\
+             \n\
+             2021-01-02\tPub\tWater\t3.00\n",
+        );
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].date, "2021-01-01");
+        assert_eq!(products[0].product, "Beer");
+        assert_eq!(products[0].product_type, "");
+        assert_eq!(products[0].place, "Pub");
+        assert_eq!(products[0].price, Some(12.5));
+        assert_eq!(products[0].quantity, Some(2.0));
+        assert_eq!(products[0].currency.as_deref(), Some("USD"));
+        assert_eq!(products[1].quantity, None);
+    }
+
+    #[test]
+    fn test_read_rows_typed_cells() {
+        let rows = vec![vec![
+            Cell::Date(Date::new(2021, 1, 1).unwrap()),
+            Cell::Text("Cerveza".to_owned()),
+            Cell::Text("Bebida".to_owned()),
+            Cell::Text("Bar".to_owned()),
+            Cell::Number(1234.5),
+            Cell::Number(3.0),
+        ]];
+        let products = read_rows(&rows, NumberFormat::Comma);
+        assert_eq!(products[0].date, "2021-01-01");
+        assert_eq!(products[0].price, Some(1234.5));
+        assert_eq!(products[0].quantity, Some(3.0));
     }
 
     #[test]
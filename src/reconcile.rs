@@ -0,0 +1,202 @@
+use crate::bank::Transaction;
+use crate::date::Date;
+use crate::duplicates::Receipt;
+use serde::Serialize;
+
+/// Largest difference, in currency units, still taken as the same amount.
+const AMOUNT_TOLERANCE: f64 = 0.01;
+
+/// A charge paired with the receipt it pays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub transaction: &'a Transaction,
+    pub receipt: &'a Receipt,
+    /// Part of the charge above the receipt total, left as a tip on the card terminal.
+    pub tip: f64,
+    pub days: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Reconciliation<'a> {
+    pub matches: Vec<Match<'a>>,
+    pub unmatched_charges: Vec<&'a Transaction>,
+    pub unmatched_receipts: Vec<&'a Receipt>,
+}
+
+/// One line of the reconciliation, for the machine readable formats.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchRow {
+    pub status: &'static str,
+    pub date: Option<String>,
+    pub amount: Option<f64>,
+    pub description: Option<String>,
+    pub receipt: Option<String>,
+    pub receipt_date: Option<String>,
+    pub receipt_total: Option<f64>,
+    pub tip: Option<f64>,
+    pub days: Option<i64>,
+}
+
+/// Pairs the charges with the receipts whose date is at most `window` days apart and whose
+/// total matches the charge, or is below it by a tip of up to `max_tip` of the total.
+/// Exact amounts are paired first, then the closest dates.
+pub fn reconcile<'a>(
+    transactions: &'a [Transaction],
+    receipts: &'a [Receipt],
+    window: i64,
+    max_tip: f64,
+) -> Reconciliation<'a> {
+    let charges = transactions
+        .iter()
+        .filter(|x| x.amount > 0.0)
+        .collect::<Vec<_>>();
+    let mut candidates = Vec::new();
+    for (charge_index, charge) in charges.iter().enumerate() {
+        for (receipt_index, receipt) in receipts.iter().enumerate() {
+            let Some(date) = Date::parse(&receipt.date) else {
+                continue;
+            };
+            let days = (charge.date.to_days() - date.to_days()).abs();
+            let tip = charge.amount - receipt.total;
+            let exact = tip.abs() <= AMOUNT_TOLERANCE;
+            let with_tip = tip > 0.0 && tip <= receipt.total * max_tip + AMOUNT_TOLERANCE;
+            if days <= window && (exact || with_tip) {
+                let tip = if exact { 0.0 } else { tip };
+                candidates.push((!exact, days, tip, charge_index, receipt_index));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.total_cmp(&b.2)));
+
+    let mut matched_charges = vec![false; charges.len()];
+    let mut matched_receipts = vec![false; receipts.len()];
+    let mut reconciliation = Reconciliation::default();
+    for (_, days, tip, charge_index, receipt_index) in candidates {
+        if matched_charges[charge_index] || matched_receipts[receipt_index] {
+            continue;
+        }
+        matched_charges[charge_index] = true;
+        matched_receipts[receipt_index] = true;
+        reconciliation.matches.push(Match {
+            transaction: charges[charge_index],
+            receipt: &receipts[receipt_index],
+            tip,
+            days,
+        });
+    }
+    reconciliation
+        .matches
+        .sort_by_key(|x| (x.transaction.date, x.receipt.source.clone()));
+    reconciliation.unmatched_charges = charges
+        .iter()
+        .zip(&matched_charges)
+        .filter(|(_, matched)| !**matched)
+        .map(|(x, _)| *x)
+        .collect();
+    reconciliation.unmatched_receipts = receipts
+        .iter()
+        .zip(&matched_receipts)
+        .filter(|(_, matched)| !**matched)
+        .map(|(x, _)| x)
+        .collect();
+    reconciliation
+}
+
+impl Reconciliation<'_> {
+    pub fn rows(&self) -> Vec<MatchRow> {
+        let charge = |status, x: &Transaction| MatchRow {
+            status,
+            date: Some(x.date.to_string()),
+            amount: Some(x.amount),
+            description: Some(x.description.clone()),
+            receipt: None,
+            receipt_date: None,
+            receipt_total: None,
+            tip: None,
+            days: None,
+        };
+        let matches = self.matches.iter().map(|x| MatchRow {
+            receipt: Some(x.receipt.source.clone()),
+            receipt_date: Some(x.receipt.date.clone()),
+            receipt_total: Some(x.receipt.total),
+            tip: Some(x.tip),
+            days: Some(x.days),
+            ..charge("matched", x.transaction)
+        });
+        let charges = self
+            .unmatched_charges
+            .iter()
+            .map(|x| charge("unmatched charge", x));
+        let receipts = self.unmatched_receipts.iter().map(|x| MatchRow {
+            status: "unmatched receipt",
+            date: None,
+            amount: None,
+            description: None,
+            receipt: Some(x.source.clone()),
+            receipt_date: Some(x.date.clone()),
+            receipt_total: Some(x.total),
+            tip: None,
+            days: None,
+        });
+        matches.chain(charges).chain(receipts).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charge(date: &str, amount: f64) -> Transaction {
+        Transaction {
+            date: Date::parse(date).unwrap(),
+            amount,
+            description: "POS".to_owned(),
+        }
+    }
+
+    fn receipt(source: &str, date: &str, total: f64) -> Receipt {
+        Receipt::new(source, date, "Bar", Vec::new(), total)
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let transactions = [
+            charge("2024-12-28", 653.0),
+            charge("2024-12-27", 100.0),
+            charge("2024-12-20", 80.0),
+            charge("2024-12-21", -50.0),
+            charge("2024-12-27", 100.0),
+        ];
+        let receipts = [
+            receipt("a", "2024-12-27", 593.0),
+            receipt("b", "2024-12-26", 100.0),
+            receipt("c", "2024-12-27", 100.0),
+            receipt("d", "2024-12-01", 80.0),
+        ];
+        let reconciliation = reconcile(&transactions, &receipts, 3, 0.2);
+        let matches = reconciliation
+            .matches
+            .iter()
+            .map(|x| (x.receipt.source.as_str(), x.days, x.tip.round()))
+            .collect::<Vec<_>>();
+        assert_eq!(matches, [("b", 1, 0.0), ("c", 0, 0.0), ("a", 1, 60.0)]);
+        assert_eq!(reconciliation.unmatched_charges.len(), 1);
+        assert_eq!(reconciliation.unmatched_charges[0].amount, 80.0);
+        assert_eq!(reconciliation.unmatched_receipts[0].source, "d");
+
+        let rows = reconciliation.rows();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[3].status, "unmatched charge");
+        assert_eq!(rows[4].receipt.as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn test_tip_limit() {
+        let transactions = [charge("2024-12-27", 130.0)];
+        let receipts = [receipt("a", "2024-12-27", 100.0)];
+        assert!(reconcile(&transactions, &receipts, 3, 0.2)
+            .matches
+            .is_empty());
+        assert_eq!(reconcile(&transactions, &receipts, 3, 0.3).matches.len(), 1);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::date::Date;
+use crate::invoice::Invoice;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Month,
+    Year,
+}
+
+/// Spending on a single product, the unit every report adds up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub date: Option<Date>,
+    pub category: String,
+    pub place: String,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub tip: f64,
+}
+
+/// A tax line of an invoice, kept apart to break reports down by tax kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaxEntry {
+    pub date: Option<Date>,
+    pub name: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Breakdown {
+    pub key: String,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub tip: f64,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaxTotal {
+    pub name: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeriodReport {
+    pub period: String,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub tip: f64,
+    pub total: f64,
+    /// Change of the total against the previous period, as a fraction.
+    pub change: Option<f64>,
+    pub categories: Vec<Breakdown>,
+    pub places: Vec<Breakdown>,
+    pub taxes: Vec<TaxTotal>,
+}
+
+/// Entries of a computed invoice, in its reporting currency.
+pub fn entries_from_invoice(invoice: &Invoice) -> (Vec<Entry>, Vec<TaxEntry>) {
+    let rate = invoice.exchange_rate();
+    let entries = invoice
+        .products()
+        .zip(invoice.allocations())
+        .map(|(product, allocation)| Entry {
+            date: Date::parse(&product.date),
+            category: product.product_type.trim().to_owned(),
+            place: product.place.trim().to_owned(),
+            subtotal: allocation.base * rate,
+            tax: allocation.tax * rate,
+            tip: allocation.tip * rate,
+        })
+        .collect();
+    let date = invoice.products().next().and_then(|x| Date::parse(&x.date));
+    let taxes = invoice
+        .tax_lines()
+        .iter()
+        .map(|x| TaxEntry {
+            date,
+            name: x.product.trim().to_owned(),
+            amount: x.price.unwrap_or(0.0) * rate,
+        })
+        .collect();
+    (entries, taxes)
+}
+
+pub fn period_key(date: Option<Date>, period: Period) -> String {
+    match (date, period) {
+        (None, _) => "unknown".to_owned(),
+        (Some(date), Period::Month) => date.month_key(),
+        (Some(date), Period::Year) => format!("{:04}", date.year),
+    }
+}
+
+/// Reads a period typed by the user, `YYYY-MM` for months and `YYYY` for years, into the
+/// key `period_key` gives the entries of that period.
+pub fn parse_period_key(value: &str, period: Period) -> Option<String> {
+    let number = |x: &str, digits: usize| -> Option<u32> {
+        if x.len() != digits || !x.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        x.parse().ok()
+    };
+    let mut parts = value.trim().split('-');
+    let year = number(parts.next()?, 4)?;
+    let date = match period {
+        Period::Month => Date::new(year as i32, number(parts.next()?, 2)?, 1)?,
+        Period::Year => Date::new(year as i32, 1, 1)?,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(period_key(Some(date), period))
+}
+
+fn previous_period_key(date: Option<Date>, period: Period) -> Option<String> {
+    match (date, period) {
+        (None, _) => None,
+        (Some(date), Period::Month) => Some(date.previous_month().month_key()),
+        (Some(date), Period::Year) => Some(format!("{:04}", date.year - 1)),
+    }
+}
+
+fn add_to_breakdown(breakdowns: &mut Vec<Breakdown>, key: &str, entry: &Entry) {
+    let index = match breakdowns.iter().position(|x| x.key == key) {
+        Some(index) => index,
+        None => {
+            breakdowns.push(Breakdown {
+                key: key.to_owned(),
+                subtotal: 0.0,
+                tax: 0.0,
+                tip: 0.0,
+                total: 0.0,
+            });
+            breakdowns.len() - 1
+        }
+    };
+    let breakdown = &mut breakdowns[index];
+    breakdown.subtotal += entry.subtotal;
+    breakdown.tax += entry.tax;
+    breakdown.tip += entry.tip;
+    breakdown.total += entry.subtotal + entry.tax + entry.tip;
+}
+
+fn report_index(
+    reports: &mut Vec<(PeriodReport, Option<String>)>,
+    date: Option<Date>,
+    period: Period,
+) -> usize {
+    let key = period_key(date, period);
+    if let Some(index) = reports.iter().position(|(x, _)| x.period == key) {
+        return index;
+    }
+    reports.push((
+        PeriodReport {
+            period: key,
+            subtotal: 0.0,
+            tax: 0.0,
+            tip: 0.0,
+            total: 0.0,
+            change: None,
+            categories: Vec::new(),
+            places: Vec::new(),
+            taxes: Vec::new(),
+        },
+        previous_period_key(date, period),
+    ));
+    reports.len() - 1
+}
+
+/// Adds entries up per period, oldest first, with undated entries at the end.
+pub fn build(entries: &[Entry], taxes: &[TaxEntry], period: Period) -> Vec<PeriodReport> {
+    let mut reports: Vec<(PeriodReport, Option<String>)> = Vec::new();
+    for entry in entries {
+        let index = report_index(&mut reports, entry.date, period);
+        let report = &mut reports[index].0;
+        report.subtotal += entry.subtotal;
+        report.tax += entry.tax;
+        report.tip += entry.tip;
+        report.total += entry.subtotal + entry.tax + entry.tip;
+        add_to_breakdown(&mut report.categories, &entry.category, entry);
+        add_to_breakdown(&mut report.places, &entry.place, entry);
+    }
+    for tax in taxes {
+        let index = report_index(&mut reports, tax.date, period);
+        let report = &mut reports[index].0;
+        match report.taxes.iter_mut().find(|x| x.name == tax.name) {
+            Some(total) => total.amount += tax.amount,
+            None => report.taxes.push(TaxTotal {
+                name: tax.name.clone(),
+                amount: tax.amount,
+            }),
+        }
+    }
+
+    let totals = reports
+        .iter()
+        .map(|(x, _)| (x.period.clone(), x.total))
+        .collect::<Vec<_>>();
+    let mut reports = reports
+        .into_iter()
+        .map(|(mut report, previous)| {
+            report.change = previous
+                .and_then(|previous| totals.iter().find(|(key, _)| *key == previous))
+                .filter(|(_, total)| *total != 0.0)
+                .map(|(_, total)| (report.total - total) / total);
+            let by_total = |a: &Breakdown, b: &Breakdown| b.total.total_cmp(&a.total);
+            report.categories.sort_by(by_total);
+            report.places.sort_by(by_total);
+            report.taxes.sort_by(|a, b| b.amount.total_cmp(&a.amount));
+            report
+        })
+        .collect::<Vec<_>>();
+    // "unknown" sorts after the numeric periods.
+    reports.sort_by(|a, b| a.period.cmp(&b.period));
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+    use crate::test_support::entry;
+
+    #[test]
+    fn test_entries_from_converted_invoice() {
+        let mut products = read_file("2025-01-04\tBeer\tAlcohol\tPub\t US$11.60");
+        let products = products.iter_mut().collect::<Vec<_>>();
+        let mut invoice = Invoice::new(products).with_exchange("MXN", 20.0);
+        invoice.calculate_taxes();
+        let (entries, taxes) = entries_from_invoice(&invoice);
+        assert!((entries[0].subtotal - 200.0).abs() < 0.001);
+        assert!((entries[0].tax - 32.0).abs() < 0.001);
+        assert!((taxes[0].amount - 32.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_parse_period_key() {
+        assert_eq!(
+            parse_period_key("2024-03", Period::Month),
+            Some("2024-03".to_owned())
+        );
+        assert_eq!(
+            parse_period_key("2024", Period::Year),
+            Some("2024".to_owned())
+        );
+        assert_eq!(parse_period_key("2024-13", Period::Month), None);
+        assert_eq!(parse_period_key("12-2024", Period::Month), None);
+        assert_eq!(parse_period_key("2024", Period::Month), None);
+        assert_eq!(parse_period_key("2024-03", Period::Year), None);
+        assert_eq!(parse_period_key("2024-3-01", Period::Month), None);
+    }
+
+    #[test]
+    fn test_monthly_report() {
+        let entries = [
+            entry("2024-12-27", "Alcohol", "walmart", 100.0),
+            entry("2024-12-28", "Comida", "walmart", 50.0),
+            entry("2025-01-03", "Alcohol", "Bar", 300.0),
+            entry("sin fecha", "Ocio", "Cine", 10.0),
+        ];
+        let taxes = [TaxEntry {
+            date: Date::parse("2024-12-27"),
+            name: "IVA".to_owned(),
+            amount: 24.0,
+        }];
+        let reports = build(&entries, &taxes, Period::Month);
+        let periods = reports
+            .iter()
+            .map(|x| x.period.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(periods, ["2024-12", "2025-01", "unknown"]);
+
+        assert!((reports[0].total - 174.0).abs() < 0.001);
+        assert_eq!(reports[0].change, None);
+        assert_eq!(reports[0].categories[0].key, "Alcohol");
+        assert_eq!(reports[0].places.len(), 1);
+        assert_eq!(reports[0].taxes[0].amount, 24.0);
+
+        assert!((reports[1].change.unwrap() - 1.0).abs() < 0.001);
+        assert_eq!(reports[2].change, None);
+    }
+
+    #[test]
+    fn test_yearly_report() {
+        let entries = [
+            entry("2024-12-27", "Alcohol", "walmart", 100.0),
+            entry("2025-01-03", "Alcohol", "Bar", 50.0),
+            entry("2025-03-03", "Comida", "Bar", 50.0),
+        ];
+        let reports = build(&entries, &[], Period::Year);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1].period, "2025");
+        assert_eq!(reports[1].categories.len(), 2);
+        assert!(reports[1].change.unwrap().abs() < 0.001);
+    }
+}
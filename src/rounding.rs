@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+
+/// Tolerance used to recognise ties that binary floats cannot represent exactly, like 2.675.
+const TIE_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RoundingScope {
+    /// Round the tax of every product line and add the rounded amounts.
+    Line,
+    /// Add the exact amounts and round the invoice totals.
+    Total,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RoundingMode {
+    HalfUp,
+    HalfEven,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundingPolicy {
+    pub scope: RoundingScope,
+    pub mode: RoundingMode,
+    pub precision: u32,
+}
+
+impl RoundingPolicy {
+    pub fn new(scope: RoundingScope, mode: RoundingMode, precision: u32) -> Self {
+        RoundingPolicy {
+            scope,
+            mode,
+            precision,
+        }
+    }
+
+    pub fn round(&self, value: f64) -> f64 {
+        let factor = 10f64.powi(self.precision as i32);
+        let scaled = value * factor;
+        let floor = scaled.floor();
+        let is_tie = (scaled - floor - 0.5).abs() < TIE_TOLERANCE;
+        let rounded = match (is_tie, self.mode) {
+            (false, _) => scaled.round(),
+            (true, RoundingMode::HalfUp) => {
+                if scaled >= 0.0 {
+                    floor + 1.0
+                } else {
+                    floor
+                }
+            }
+            (true, RoundingMode::HalfEven) => {
+                if floor % 2.0 == 0.0 {
+                    floor
+                } else {
+                    floor + 1.0
+                }
+            }
+        };
+        rounded / factor
+    }
+
+    /// Rounds a single line amount, leaving it untouched when rounding happens on totals.
+    pub fn round_line(&self, value: f64) -> f64 {
+        match self.scope {
+            RoundingScope::Line => self.round(value),
+            RoundingScope::Total => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_up() {
+        let policy = RoundingPolicy::new(RoundingScope::Total, RoundingMode::HalfUp, 2);
+        assert_eq!(policy.round(0.125), 0.13);
+        assert_eq!(policy.round(2.675), 2.68);
+        assert_eq!(policy.round(-0.125), -0.13);
+        assert_eq!(policy.round(1.234), 1.23);
+    }
+
+    #[test]
+    fn test_half_even() {
+        let policy = RoundingPolicy::new(RoundingScope::Total, RoundingMode::HalfEven, 2);
+        assert_eq!(policy.round(0.125), 0.12);
+        assert_eq!(policy.round(0.135), 0.14);
+        assert_eq!(policy.round(2.675), 2.68);
+        assert_eq!(policy.round(1.236), 1.24);
+    }
+
+    #[test]
+    fn test_precision() {
+        let policy = RoundingPolicy::new(RoundingScope::Line, RoundingMode::HalfUp, 0);
+        assert_eq!(policy.round(115.5), 116.0);
+        assert_eq!(policy.round_line(115.4), 115.0);
+
+        let policy = RoundingPolicy::new(RoundingScope::Total, RoundingMode::HalfUp, 0);
+        assert_eq!(policy.round_line(115.4), 115.4);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::amount::NumberFormat;
+use crate::date::Date;
+use crate::product::Product;
+use crate::reader::{self, Cell};
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use std::path::Path;
+
+const EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+pub fn is_workbook(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| EXTENSIONS.iter().any(|y| x.eq_ignore_ascii_case(y)))
+}
+
+fn cell(data: &Data) -> Cell {
+    match data {
+        Data::Int(x) => Cell::Number(*x as f64),
+        Data::Float(x) => Cell::Number(*x),
+        Data::DateTime(x) if x.is_datetime() => {
+            let (year, month, day, ..) = x.to_ymd_hms_milli();
+            Date::new(year.into(), month.into(), day.into())
+                .map_or(Cell::Text(String::new()), Cell::Date)
+        }
+        Data::DateTimeIso(x) => {
+            Date::parse(x.get(..10).unwrap_or(x)).map_or_else(|| Cell::Text(x.clone()), Cell::Date)
+        }
+        Data::Empty | Data::Error(_) => Cell::Text(String::new()),
+        x => Cell::Text(x.to_string()),
+    }
+}
+
+pub fn read_range(range: &Range<Data>, format: NumberFormat) -> Vec<Product> {
+    let rows = range
+        .rows()
+        .map(|x| x.iter().map(cell).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    reader::read_rows(&rows, format)
+}
+
+/// Reads the products of an XLSX, XLS or ODS workbook from the named sheet, or the first
+/// one when no sheet is given.
+pub fn read_workbook(
+    path: &Path,
+    sheet: Option<&str>,
+    format: NumberFormat,
+) -> Result<Vec<Product>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|x| x.to_string())?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) => names
+            .iter()
+            .find(|x| x.eq_ignore_ascii_case(sheet))
+            .ok_or_else(|| format!("no sheet {:?}, found: {}", sheet, names.join(", ")))?,
+        None => names.first().ok_or("the workbook has no sheets")?,
+    }
+    .clone();
+    let range = workbook.worksheet_range(&name).map_err(|x| x.to_string())?;
+    Ok(read_range(&range, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    #[test]
+    fn test_read_range() {
+        let mut range = Range::new((0, 0), (2, 3));
+        let header = ["Fecha", "Producto", "Precio", "Cantidad"];
+        for (column, name) in header.iter().enumerate() {
+            range.set_value((0, column as u32), Data::String(name.to_string()));
+        }
+        let date = ExcelDateTime::new(45653.0, ExcelDateTimeType::DateTime, false);
+        range.set_value((1, 0), Data::DateTime(date));
+        range.set_value((1, 1), Data::String("Vino Tinto".to_owned()));
+        range.set_value((1, 2), Data::Float(1148.5));
+        range.set_value((1, 3), Data::Int(2));
+        range.set_value((2, 0), Data::DateTimeIso("2024-12-28T00:00:00".to_owned()));
+        range.set_value((2, 1), Data::String("Pan".to_owned()));
+        range.set_value((2, 2), Data::String("1.234,50".to_owned()));
+
+        let products = read_range(&range, NumberFormat::Auto);
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].date, "2024-12-27");
+        assert_eq!(products[0].price, Some(1148.5));
+        assert_eq!(products[0].quantity, Some(2.0));
+        assert_eq!(products[1].date, "2024-12-28");
+        assert_eq!(products[1].price, Some(1234.5));
+        assert_eq!(products[1].quantity, None);
+    }
+
+    #[test]
+    fn test_is_workbook() {
+        assert!(is_workbook(Path::new("receipts.XLSX")));
+        assert!(is_workbook(Path::new("receipts.ods")));
+        assert!(!is_workbook(Path::new("receipts.tsv")));
+        assert!(!is_workbook(Path::new("-")));
+    }
+}
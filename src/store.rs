@@ -0,0 +1,166 @@
+use crate::product::Product;
+use crate::reader::normalize;
+use crate::tax_engine::{self, PricingMode};
+use serde::Deserialize;
+
+/// A store as written in the stores file:
+///
+/// ```toml
+/// [[store]]
+/// name = "Walmart"
+/// aliases = ["wal-mart", "walmart express"]
+/// prices_include_tax = true
+/// tax_profile = "mx-ieps"
+///
+/// [[store]]
+/// name = "La Cantina"
+/// tips = true
+/// tip_percentage = 10
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Store {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Overrides the pricing mode of the tax profile.
+    pub prices_include_tax: Option<bool>,
+    /// Whether the receipts of this store usually leave a tip on top.
+    #[serde(default)]
+    pub tips: bool,
+    /// Tip added when `tips` is set, as a percentage such as 10.
+    pub tip_percentage: Option<f64>,
+    /// Jurisdiction code used for this store, like `mx-ieps` or `us-ca`.
+    pub tax_profile: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StoreRegistry {
+    #[serde(default)]
+    store: Vec<Store>,
+}
+
+impl Store {
+    pub fn pricing_mode(&self) -> Option<PricingMode> {
+        self.prices_include_tax.map(|x| match x {
+            true => PricingMode::Inclusive,
+            false => PricingMode::Exclusive,
+        })
+    }
+
+    /// Tip share to add to the receipts, as a fraction of the pre-tax price.
+    pub fn tip_rate(&self) -> Option<f64> {
+        match self.tips {
+            true => self.tip_percentage.map(|x| x / 100.0),
+            false => None,
+        }
+    }
+}
+
+impl StoreRegistry {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let registry: StoreRegistry = toml::from_str(content).map_err(|x| x.to_string())?;
+        for store in &registry.store {
+            if let Some(profile) = &store.tax_profile {
+                if tax_engine::from_code(profile).is_none() {
+                    return Err(format!(
+                        "unknown tax profile {:?} for {}",
+                        profile, store.name
+                    ));
+                }
+            }
+            if store.tips && store.tip_percentage.is_none() {
+                return Err(format!("{} has tips but no tip_percentage", store.name));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Finds a store by its name or any alias, ignoring case and spacing.
+    pub fn find(&self, place: &str) -> Option<&Store> {
+        let place = normalize(place);
+        self.store.iter().find(|x| {
+            std::iter::once(&x.name)
+                .chain(x.aliases.iter())
+                .any(|x| normalize(x) == place)
+        })
+    }
+
+    /// Replaces the places of the products with the store names, returning the
+    /// places that are not registered.
+    pub fn normalize_places(&self, products: &mut [Product]) -> Vec<String> {
+        let mut unknown: Vec<String> = Vec::new();
+        for product in products.iter_mut() {
+            match self.find(&product.place) {
+                Some(store) => product.place = store.name.clone(),
+                None => {
+                    let place = product.place.trim().to_owned();
+                    if !unknown.contains(&place) {
+                        unknown.push(place);
+                    }
+                }
+            }
+        }
+        unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_file;
+
+    const STORES: &str = r#"
+[[store]]
+name = "Walmart"
+aliases = ["wal-mart"]
+tax_profile = "mx-ieps"
+
+[[store]]
+name = "Costco US"
+prices_include_tax = false
+tax_profile = "us-ca"
+
+[[store]]
+name = "La Cantina"
+aliases = ["Restaurant", "Cantina Jalapeño"]
+tips = true
+tip_percentage = 10
+"#;
+
+    #[test]
+    fn test_find() {
+        let stores = StoreRegistry::from_toml(STORES).unwrap();
+        assert_eq!(stores.find(" WALMART").unwrap().name, "Walmart");
+        assert_eq!(stores.find("Wal-Mart").unwrap().name, "Walmart");
+        assert_eq!(stores.find("restaurant").unwrap().tip_rate(), Some(0.1));
+        assert_eq!(stores.find("CANTINA JALAPEÑO").unwrap().name, "La Cantina");
+        assert_eq!(
+            stores.find("costco us").unwrap().pricing_mode(),
+            Some(PricingMode::Exclusive)
+        );
+        assert!(stores.find("Oxxo").is_none());
+    }
+
+    #[test]
+    fn test_normalize_places() {
+        let stores = StoreRegistry::from_toml(STORES).unwrap();
+        let mut products = read_file(
+            "
+            2024-12-27	Jabón	Limpieza	walmart	 $67.00
+            2024-12-27	Rummy	Ocio	name	 $185.00
+            2024-12-27	Cerveza	Alcohol	Restaurant	 $35.00
+            2024-12-27	Agua	Bebida	name	 $15.00
+            ",
+        );
+        let unknown = stores.normalize_places(&mut products);
+        assert_eq!(products[0].place, "Walmart");
+        assert_eq!(products[2].place, "La Cantina");
+        assert_eq!(unknown, ["name"]);
+    }
+
+    #[test]
+    fn test_invalid_stores() {
+        assert!(StoreRegistry::from_toml("[[store]]\nname = \"A\"\ntax_profile = \"fr\"").is_err());
+        assert!(StoreRegistry::from_toml("[[store]]\nname = \"A\"\ntips = true").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::product::Product;
+use clap::ValueEnum;
+use std::fmt::{Debug, Display};
+
+pub const MX_IVA: f64 = 0.16;
+
+const MX_IEPS: [(&str, f64); 4] = [
+    ("Alcohol", 0.265),
+    ("Tabaco", 1.60),
+    ("Botanas", 0.08),
+    ("Dulces", 0.08),
+];
+
+const ES_IVA_GENERAL: f64 = 0.21;
+const ES_IVA_REDUCED: f64 = 0.10;
+const ES_IVA_SUPER_REDUCED: f64 = 0.04;
+
+const ES_REDUCED_TYPES: [&str; 6] = [
+    "Comida",
+    "Restaurante",
+    "Bebida",
+    "Hotel",
+    "Transporte",
+    "Agua",
+];
+
+const ES_SUPER_REDUCED_TYPES: [&str; 7] = [
+    "Pan",
+    "Leche",
+    "Huevos",
+    "Frutas",
+    "Verduras",
+    "Libros",
+    "Medicinas",
+];
+
+/// Base state sales tax rates, without county or city surcharges.
+const US_STATE_RATES: [(&str, f64); 51] = [
+    ("AL", 0.04),
+    ("AK", 0.0),
+    ("AZ", 0.056),
+    ("AR", 0.065),
+    ("CA", 0.0725),
+    ("CO", 0.029),
+    ("CT", 0.0635),
+    ("DE", 0.0),
+    ("DC", 0.06),
+    ("FL", 0.06),
+    ("GA", 0.04),
+    ("HI", 0.04),
+    ("ID", 0.06),
+    ("IL", 0.0625),
+    ("IN", 0.07),
+    ("IA", 0.06),
+    ("KS", 0.065),
+    ("KY", 0.06),
+    ("LA", 0.05),
+    ("ME", 0.055),
+    ("MD", 0.06),
+    ("MA", 0.0625),
+    ("MI", 0.06),
+    ("MN", 0.06875),
+    ("MS", 0.07),
+    ("MO", 0.04225),
+    ("MT", 0.0),
+    ("NE", 0.055),
+    ("NV", 0.0685),
+    ("NH", 0.0),
+    ("NJ", 0.06625),
+    ("NM", 0.04875),
+    ("NY", 0.04),
+    ("NC", 0.0475),
+    ("ND", 0.05),
+    ("OH", 0.0575),
+    ("OK", 0.045),
+    ("OR", 0.0),
+    ("PA", 0.06),
+    ("RI", 0.07),
+    ("SC", 0.06),
+    ("SD", 0.042),
+    ("TN", 0.07),
+    ("TX", 0.0625),
+    ("UT", 0.061),
+    ("VT", 0.06),
+    ("VA", 0.053),
+    ("WA", 0.065),
+    ("WV", 0.06),
+    ("WI", 0.05),
+    ("WY", 0.04),
+];
+
+/// How receipt prices relate to the taxes charged on them.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PricingMode {
+    /// Prices contain the taxes, the base is `price / (1 + rate)`.
+    Inclusive,
+    /// Prices are the base and the taxes are added on top.
+    Exclusive,
+}
+
+impl Display for PricingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PricingMode::Inclusive => write!(f, "tax-inclusive"),
+            PricingMode::Exclusive => write!(f, "tax-exclusive"),
+        }
+    }
+}
+
+/// A single tax applied to a product, as a share of its pre-tax price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaxRate {
+    pub name: &'static str,
+    pub rate: f64,
+}
+
+pub trait TaxEngine: Debug {
+    fn name(&self) -> String;
+
+    fn rates(&self, product: &Product) -> Vec<TaxRate>;
+
+    /// The general consumption tax, which rates set per product replace.
+    fn primary_tax(&self) -> &'static str;
+
+    /// The rates with the primary tax charged at `rate` instead of the jurisdiction's,
+    /// compounded over the other taxes the same way.
+    fn rates_with_primary(&self, product: &Product, rate: f64) -> Vec<TaxRate> {
+        let name = self.primary_tax();
+        let mut rates = self.rates(product);
+        match rates.iter_mut().find(|x| x.name == name) {
+            Some(x) => x.rate = rate,
+            None => rates.push(TaxRate { name, rate }),
+        }
+        rates
+    }
+
+    /// Pricing mode used by receipts of this jurisdiction.
+    fn pricing_mode(&self) -> PricingMode {
+        PricingMode::Inclusive
+    }
+
+    fn rate(&self, product: &Product) -> f64 {
+        self.rates(product).iter().map(|x| x.rate).sum()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Mexico {
+    ieps: bool,
+}
+
+impl Mexico {
+    pub fn new(ieps: bool) -> Self {
+        Mexico { ieps }
+    }
+
+    fn ieps_rate(&self, product: &Product) -> f64 {
+        if !self.ieps {
+            return 0.0;
+        }
+        MX_IEPS
+            .iter()
+            .find(|(product_type, _)| product.product_type.eq_ignore_ascii_case(product_type))
+            .map_or(0.0, |(_, rate)| *rate)
+    }
+}
+
+impl TaxEngine for Mexico {
+    fn name(&self) -> String {
+        if self.ieps {
+            "Mexico (IVA/IEPS)".to_owned()
+        } else {
+            "Mexico (IVA)".to_owned()
+        }
+    }
+
+    fn primary_tax(&self) -> &'static str {
+        "IVA"
+    }
+
+    fn rates(&self, product: &Product) -> Vec<TaxRate> {
+        self.rates_with_primary(product, MX_IVA)
+    }
+
+    fn rates_with_primary(&self, product: &Product, rate: f64) -> Vec<TaxRate> {
+        let ieps = self.ieps_rate(product);
+        if ieps == 0.0 {
+            return vec![TaxRate { name: "IVA", rate }];
+        }
+        // IVA is charged over the price that already includes IEPS.
+        vec![
+            TaxRate {
+                name: "IEPS",
+                rate: ieps,
+            },
+            TaxRate {
+                name: "IVA",
+                rate: rate * (1.0 + ieps),
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Spain;
+
+impl TaxEngine for Spain {
+    fn name(&self) -> String {
+        "Spain (IVA)".to_owned()
+    }
+
+    fn primary_tax(&self) -> &'static str {
+        "IVA"
+    }
+
+    fn rates(&self, product: &Product) -> Vec<TaxRate> {
+        let is_type = |x: &&str| product.product_type.eq_ignore_ascii_case(x);
+        let rate = if ES_SUPER_REDUCED_TYPES.iter().any(is_type) {
+            ES_IVA_SUPER_REDUCED
+        } else if ES_REDUCED_TYPES.iter().any(is_type) {
+            ES_IVA_REDUCED
+        } else {
+            ES_IVA_GENERAL
+        };
+        vec![TaxRate { name: "IVA", rate }]
+    }
+}
+
+#[derive(Debug)]
+pub struct UnitedStates {
+    state: &'static str,
+    rate: f64,
+}
+
+impl UnitedStates {
+    pub fn new(state: &str) -> Option<Self> {
+        US_STATE_RATES
+            .iter()
+            .find(|(code, _)| code.eq_ignore_ascii_case(state))
+            .map(|(state, rate)| UnitedStates { state, rate: *rate })
+    }
+}
+
+impl TaxEngine for UnitedStates {
+    fn name(&self) -> String {
+        format!("United States ({} sales tax)", self.state)
+    }
+
+    fn primary_tax(&self) -> &'static str {
+        "Sales tax"
+    }
+
+    fn rates(&self, _product: &Product) -> Vec<TaxRate> {
+        vec![TaxRate {
+            name: "Sales tax",
+            rate: self.rate,
+        }]
+    }
+
+    fn pricing_mode(&self) -> PricingMode {
+        PricingMode::Exclusive
+    }
+}
+
+/// Builds an engine from a jurisdiction code: `mx`, `mx-ieps`, `es` or `us-<state>`.
+pub fn from_code(code: &str) -> Option<Box<dyn TaxEngine>> {
+    let code = code.trim().to_lowercase();
+    match code.as_str() {
+        "mx" => Some(Box::new(Mexico::new(false))),
+        "mx-ieps" => Some(Box::new(Mexico::new(true))),
+        "es" => Some(Box::new(Spain)),
+        _ => code
+            .strip_prefix("us-")
+            .and_then(UnitedStates::new)
+            .map(|x| Box::new(x) as Box<dyn TaxEngine>),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(product_type: &str) -> Product {
+        Product {
+            date: "2021-01-01".to_owned(),
+            product: "Cerveza".to_owned(),
+            product_type: product_type.to_owned(),
+            place: "Bar".to_owned(),
+            price: Some(100.0),
+            quantity: None,
+            currency: None,
+            adjustments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_mexico_rates() {
+        let engine = Mexico::default();
+        assert_eq!(engine.rate(&product("Alcohol")), MX_IVA);
+
+        let engine = Mexico::new(true);
+        let rates = engine.rates(&product("Alcohol"));
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].name, "IEPS");
+        assert!((engine.rate(&product("Alcohol")) - 0.4674).abs() < 0.0001);
+        assert_eq!(engine.rate(&product("Abarrotes")), MX_IVA);
+    }
+
+    #[test]
+    fn test_spain_rates() {
+        let engine = Spain;
+        assert_eq!(engine.rate(&product("Alcohol")), 0.21);
+        assert_eq!(engine.rate(&product("comida")), 0.10);
+        assert_eq!(engine.rate(&product("Pan")), 0.04);
+    }
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(
+            from_code("mx").unwrap().pricing_mode(),
+            PricingMode::Inclusive
+        );
+        assert!(from_code("ES").is_some());
+        let engine = from_code("us-ca").unwrap();
+        assert_eq!(engine.pricing_mode(), PricingMode::Exclusive);
+        assert_eq!(engine.rate(&product("Ocio")), 0.0725);
+        assert!(from_code("us-xx").is_none());
+        assert!(from_code("fr").is_none());
+    }
+}
@@ -0,0 +1,29 @@
+//! Builders for the records several test modules need.
+
+use crate::date::Date;
+use crate::history::Purchase;
+use crate::report::Entry;
+
+/// A purchase whose price includes 16% VAT.
+pub fn purchase(date: &str, place: &str, original_price: f64, quantity: Option<f64>) -> Purchase {
+    Purchase {
+        date: date.to_owned(),
+        place: place.to_owned(),
+        original_price,
+        price: original_price / 1.16,
+        tax: original_price - original_price / 1.16,
+        quantity,
+    }
+}
+
+/// A report entry with 16% VAT over the subtotal and no tip.
+pub fn entry(date: &str, category: &str, place: &str, subtotal: f64) -> Entry {
+    Entry {
+        date: Date::parse(date),
+        category: category.to_owned(),
+        place: place.to_owned(),
+        subtotal,
+        tax: subtotal * 0.16,
+        tip: 0.0,
+    }
+}
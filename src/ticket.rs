@@ -0,0 +1,321 @@
+use crate::amount::{self, NumberFormat};
+use crate::date::Date;
+use crate::product::Product;
+use regex::Regex;
+
+/// Largest difference, in currency units, accepted between the ticket total and the items.
+const TOLERANCE: f64 = 0.015;
+
+/// Lines about the payment, which are neither items nor part of the total.
+const PAYMENT_WORDS: [&str; 6] = ["EFECTIVO", "CAMBIO", "TARJETA", "PAGO", "CASH", "CHANGE"];
+
+/// A purchased item, as transcribed from the ticket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketLine {
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit_price: Option<f64>,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Subtotal,
+    Tax(&'static str),
+    Total,
+    Tip,
+    Payment,
+}
+
+/// How the items of a ticket add up to its total.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verification {
+    /// The ticket has no total line to check against.
+    NoTotal,
+    /// The items already include the taxes.
+    TaxesIncluded,
+    /// The taxes are added to the items, as in `SUBTOTAL + IVA = TOTAL`.
+    TaxesAdded,
+    /// The total differs from the items by this amount.
+    Mismatch(f64),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ticket {
+    pub date: Option<Date>,
+    pub place: Option<String>,
+    pub items: Vec<TicketLine>,
+    pub subtotal: Option<f64>,
+    pub taxes: Vec<(&'static str, f64)>,
+    pub total: Option<f64>,
+    pub tip: Option<f64>,
+    /// Lines that are neither items nor control lines.
+    pub unrecognized: Vec<String>,
+}
+
+/// Name of the line without punctuation, so `I.V.A.:` reads as `IVA`. Keywords must be
+/// whole words, so `TIPICO` is still an item.
+fn control(name: &str) -> Option<Control> {
+    let name = name
+        .to_uppercase()
+        .chars()
+        .filter(|x| x.is_alphanumeric() || x.is_whitespace())
+        .collect::<String>();
+    let starts = |x: &str| {
+        name.strip_prefix(x)
+            .is_some_and(|rest| !rest.starts_with(char::is_alphabetic))
+    };
+    if starts("SUBTOTAL") || starts("SUB TOTAL") {
+        Some(Control::Subtotal)
+    } else if starts("TOTAL") {
+        Some(Control::Total)
+    } else if starts("IVA") {
+        Some(Control::Tax("IVA"))
+    } else if starts("IEPS") {
+        Some(Control::Tax("IEPS"))
+    } else if starts("PROPINA") || starts("TIP") {
+        Some(Control::Tip)
+    } else if PAYMENT_WORDS.iter().any(|x| starts(x)) {
+        Some(Control::Payment)
+    } else {
+        None
+    }
+}
+
+fn line_date(line: &str) -> Option<Date> {
+    Date::parse(line).or_else(|| line.split_whitespace().find_map(Date::parse))
+}
+
+impl Ticket {
+    /// Reads a typed or OCR'd ticket: a place and a date before the items, lines such as
+    /// `SIDRA 88.45` or `2 x CERVEZA 35.00 70.00`, and control lines like `TOTAL 158.45`.
+    pub fn parse(text: &str, format: NumberFormat) -> Ticket {
+        let quantity_prefix = Regex::new(r"^(\d+(?:[.,]\d+)?)\s*[xX*]\s+(.+)$").unwrap();
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>();
+        let format = match format {
+            NumberFormat::Auto => {
+                amount::detect(lines.iter().filter_map(|x| x.split_whitespace().last()))
+            }
+            x => x,
+        };
+
+        let mut ticket = Ticket::default();
+        for line in lines {
+            let mut words = line.split_whitespace().collect::<Vec<_>>();
+            let amount = words
+                .last()
+                .filter(|x| x.chars().any(|x| x.is_ascii_digit()))
+                .and_then(|x| amount::parse(x, format));
+            let Some(amount) = amount.filter(|_| words.len() > 1) else {
+                match line_date(line) {
+                    Some(date) if ticket.date.is_none() => ticket.date = Some(date),
+                    _ if ticket.place.is_none() && ticket.items.is_empty() => {
+                        ticket.place = Some(line.to_owned())
+                    }
+                    _ => ticket.unrecognized.push(line.to_owned()),
+                }
+                continue;
+            };
+            words.pop();
+            let name = words.join(" ");
+            match control(&name) {
+                Some(Control::Subtotal) => ticket.subtotal = Some(amount),
+                Some(Control::Total) => ticket.total = Some(amount),
+                Some(Control::Tax(tax)) => ticket.taxes.push((tax, amount)),
+                Some(Control::Tip) => ticket.tip = Some(ticket.tip.unwrap_or(0.0) + amount),
+                Some(Control::Payment) => {}
+                None if line_date(line).is_some() && ticket.items.is_empty() => {
+                    ticket.date = ticket.date.or(line_date(line))
+                }
+                None => ticket
+                    .items
+                    .push(Ticket::item(&name, amount, format, &quantity_prefix)),
+            }
+        }
+        ticket
+    }
+
+    /// Splits the quantity and the unit price from the name, keeping the line amount.
+    fn item(name: &str, amount: f64, format: NumberFormat, quantity_prefix: &Regex) -> TicketLine {
+        let (quantity, name) = match quantity_prefix.captures(name) {
+            Some(x) => (amount::parse(&x[1], format), x[2].to_owned()),
+            None => (None, name.to_owned()),
+        };
+        let mut words = name.split_whitespace().collect::<Vec<_>>();
+        let unit_price = words
+            .last()
+            .filter(|x| quantity.is_some() || x.contains(['.', ',']))
+            .and_then(|x| amount::parse(x, format))
+            .filter(|_| words.len() > 1);
+        if unit_price.is_some() {
+            words.pop();
+        }
+        TicketLine {
+            name: words.join(" "),
+            quantity,
+            unit_price,
+            amount,
+        }
+    }
+
+    pub fn items_total(&self) -> f64 {
+        self.items.iter().fold(0.0, |acc, x| acc + x.amount)
+    }
+
+    pub fn taxes_total(&self) -> f64 {
+        self.taxes.iter().fold(0.0, |acc, (_, x)| acc + x)
+    }
+
+    /// Checks the total line against the items and tip, with or without the tax lines.
+    pub fn verify(&self) -> Verification {
+        let Some(total) = self.total else {
+            return Verification::NoTotal;
+        };
+        let expected = self.items_total() + self.tip.unwrap_or(0.0);
+        if (total - expected).abs() <= TOLERANCE {
+            Verification::TaxesIncluded
+        } else if !self.taxes.is_empty()
+            && (total - expected - self.taxes_total()).abs() <= TOLERANCE
+        {
+            Verification::TaxesAdded
+        } else {
+            Verification::Mismatch(total - expected)
+        }
+    }
+
+    /// Items, tax lines and tip as products. When the taxes are added to the items, every
+    /// item takes its share of them, since tax lines are read from tax-inclusive prices.
+    pub fn products(&self) -> Vec<Product> {
+        let date = self.date.map_or(String::new(), |x| x.to_string());
+        let place = self.place.clone().unwrap_or_default();
+        let items_total = self.items_total();
+        let gross_up = match self.verify() {
+            Verification::TaxesAdded if items_total != 0.0 => {
+                1.0 + self.taxes_total() / items_total
+            }
+            _ => 1.0,
+        };
+        let product = |name: &str, product_type: &str, price: f64, quantity: Option<f64>| Product {
+            date: date.clone(),
+            product: name.to_owned(),
+            product_type: product_type.to_owned(),
+            place: place.clone(),
+            price: Some(price),
+            quantity,
+            currency: None,
+            adjustments: Vec::new(),
+        };
+        let mut products = self
+            .items
+            .iter()
+            .map(|x| product(&x.name, "", x.amount * gross_up, x.quantity))
+            .collect::<Vec<_>>();
+        for (name, amount) in &self.taxes {
+            products.push(product(name, "Impuestos", *amount, None));
+        }
+        if let Some(tip) = self.tip {
+            products.push(product("Propina", "Propina", tip, None));
+        }
+        products
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ticket = Ticket::parse(
+            "
+            WALMART SUPERCENTER
+            27/12/2024 14:35
+            SIDRA 88.45
+            2 x CERVEZA 35.00 70.00
+            COCA 600ML 18.00
+            DESCUENTO -10.00
+            SUBTOTAL 166.45
+            I.V.A. 16% 22.96
+            TOTAL 166.45
+            EFECTIVO 200.00
+            CAMBIO 33.55
+            GRACIAS POR SU COMPRA
+            ",
+            NumberFormat::Auto,
+        );
+        assert_eq!(ticket.place.as_deref(), Some("WALMART SUPERCENTER"));
+        assert_eq!(ticket.date, Date::new(2024, 12, 27));
+        assert_eq!(ticket.items.len(), 4);
+        assert_eq!(
+            ticket.items[1],
+            TicketLine {
+                name: "CERVEZA".to_owned(),
+                quantity: Some(2.0),
+                unit_price: Some(35.0),
+                amount: 70.0,
+            }
+        );
+        assert_eq!(ticket.items[2].name, "COCA 600ML");
+        assert_eq!(ticket.items[2].unit_price, None);
+        assert_eq!(ticket.items[3].amount, -10.0);
+        assert_eq!(ticket.taxes, [("IVA", 22.96)]);
+        assert_eq!(ticket.unrecognized, ["GRACIAS POR SU COMPRA"]);
+        assert_eq!(ticket.verify(), Verification::TaxesIncluded);
+
+        let products = ticket.products();
+        assert_eq!(products.len(), 5);
+        assert_eq!(products[1].quantity, Some(2.0));
+        assert_eq!(products[4].product_type, "Impuestos");
+        assert_eq!(products[0].date, "2024-12-27");
+    }
+
+    #[test]
+    fn test_taxes_added() {
+        let ticket = Ticket::parse(
+            "
+            TACOS 100.00
+            AGUA 50.00
+            SUBTOTAL 150.00
+            IVA 24.00
+            PROPINA 15.00
+            TOTAL 189.00
+            ",
+            NumberFormat::Auto,
+        );
+        assert_eq!(ticket.verify(), Verification::TaxesAdded);
+        let products = ticket.products();
+        assert!((products[0].price.unwrap() - 116.0).abs() < 0.001);
+        assert!((products[1].price.unwrap() - 58.0).abs() < 0.001);
+        assert_eq!(products[3].product, "Propina");
+    }
+
+    #[test]
+    fn test_items_starting_with_keywords() {
+        let ticket = Ticket::parse(
+            "TIPICO 50.00\nTOTALITOS 20.00\nIVAN'S SPECIAL 30.00\nTOTAL 100.00",
+            NumberFormat::Auto,
+        );
+        assert_eq!(ticket.items.len(), 3);
+        assert_eq!(ticket.items[0].name, "TIPICO");
+        assert_eq!(ticket.items[2].name, "IVAN'S SPECIAL");
+        assert_eq!(ticket.verify(), Verification::TaxesIncluded);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let ticket = Ticket::parse("SIDRA 88,45\nPAN 20,00\nTOTAL 100,00", NumberFormat::Auto);
+        assert_eq!(ticket.items_total(), 108.45);
+        match ticket.verify() {
+            Verification::Mismatch(x) => assert!((x + 8.45).abs() < 0.001),
+            x => panic!("unexpected {:?}", x),
+        }
+        assert_eq!(
+            Ticket::parse("SIDRA 88.45", NumberFormat::Auto).verify(),
+            Verification::NoTotal
+        );
+    }
+}